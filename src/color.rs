@@ -0,0 +1,206 @@
+use std::io::Write;
+
+/// A color of any palette a terminal may understand,
+/// see `ColorSupport` for how it is shown on terminals with fewer colors
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum Color {
+    Default,
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Purple,
+    Cyan,
+    White,
+    /// one of the 8 bright colors,0 bright black to 7 bright white
+    Bright(u8),
+    /// one of the 256 colors of the xterm palette
+    Indexed(u8),
+    Rgb(u8,u8,u8),
+}
+
+/// xterm default values of the 16 basic and bright colors
+const ANSI_RGB:[(u8,u8,u8);16] = [
+    (0,0,0),(205,0,0),(0,205,0),(205,205,0),(0,0,238),(205,0,205),(0,205,205),(229,229,229),
+    (127,127,127),(255,0,0),(0,255,0),(255,255,0),(92,92,255),(255,0,255),(0,255,255),(255,255,255),
+];
+/// the six levels of each channel in the 6x6x6 cube of the 256 color palette
+const CUBE_LEVELS:[u8;6] = [0,95,135,175,215,255];
+
+impl Color {
+    pub fn is_default(&self)->bool{
+        matches!(self, Color::Default)
+    }
+    /// index in the 16 color palette,None for the default color and the bigger palettes
+    fn ansi_index(&self)->Option<u8>{
+        match self {
+            Color::Black  => Some(0),
+            Color::Red    => Some(1),
+            Color::Green  => Some(2),
+            Color::Yellow => Some(3),
+            Color::Blue   => Some(4),
+            Color::Purple => Some(5),
+            Color::Cyan   => Some(6),
+            Color::White  => Some(7),
+            Color::Bright(n) => Some(8+n%8),
+            _ => None
+        }
+    }
+    fn from_ansi_index(index:u8)->Color{
+        match index {
+            0 => Color::Black,
+            1 => Color::Red,
+            2 => Color::Green,
+            3 => Color::Yellow,
+            4 => Color::Blue,
+            5 => Color::Purple,
+            6 => Color::Cyan,
+            7 => Color::White,
+            n => Color::Bright(n-8)
+        }
+    }
+    fn to_rgb(self)->Option<(u8,u8,u8)>{
+        match self {
+            Color::Default => None,
+            Color::Rgb(r,g,b) => Some((r,g,b)),
+            Color::Indexed(n) if n < 16 => Some(ANSI_RGB[n as usize]),
+            Color::Indexed(n) if n < 232 => {
+                let n = n-16;
+                Some((CUBE_LEVELS[(n/36) as usize],CUBE_LEVELS[(n/6%6) as usize],CUBE_LEVELS[(n%6) as usize]))
+            },
+            Color::Indexed(n) => {
+                let grey = 8+10*(n-232);
+                Some((grey,grey,grey))
+            },
+            basic => basic.ansi_index().map(|index| ANSI_RGB[index as usize])
+        }
+    }
+    /// a color name like red or bright_red,a palette index or #rrggbb
+    pub fn parse(value:&str)->Option<Color>{
+        const NAMES:[&str;8] = ["black","red","green","yellow","blue","purple","cyan","white"];
+        let value = value.to_ascii_lowercase().replace('-', "_");
+        if value == "default"{
+            return Some(Color::Default);
+        }
+        if let Some(index) = NAMES.iter().position(|name| *name == value){
+            return Some(Color::from_ansi_index(index as u8));
+        }
+        if let Some(index) = value.strip_prefix("bright_").and_then(|name| NAMES.iter().position(|n| *n == name)){
+            return Some(Color::Bright(index as u8));
+        }
+        if let Some(hex) = value.strip_prefix('#'){
+            let channel = |i:usize| hex.get(i..i+2).and_then(|digits| u8::from_str_radix(digits, 16).ok());
+            return match hex.len() {
+                6 => Some(Color::Rgb(channel(0)?,channel(2)?,channel(4)?)),
+                _ => None
+            };
+        }
+        value.parse().ok().map(Color::Indexed)
+    }
+    /// the nearest color the terminal can show
+    pub fn fit(self,support:ColorSupport)->Color{
+        match (self,support) {
+            (_,ColorSupport::Mono) => Color::Default,
+            (_,ColorSupport::TrueColor) |
+            (Color::Indexed(_),ColorSupport::Ansi256) => self,
+            (Color::Rgb(r,g,b),ColorSupport::Ansi256) => Color::Indexed(nearest_indexed((r,g,b))),
+            (Color::Indexed(_) | Color::Rgb(..),ColorSupport::Ansi16) => match self.to_rgb() {
+                Some(rgb) => Color::from_ansi_index(nearest_ansi(rgb)),
+                None => Color::Default
+            },
+            _ => self
+        }
+    }
+    /// push the SGR parameters of the color,for the background when back is true
+    pub fn encode_ascii(&self,buffer:&mut Vec<u8>,back:bool){
+        let layer = if back {4} else {3};
+        match self {
+            Color::Default => write!(buffer,"{}9",layer),
+            Color::Indexed(n) => write!(buffer,"{}8;5;{}",layer,n),
+            Color::Rgb(r,g,b) => write!(buffer,"{}8;2;{};{};{}",layer,r,g,b),
+            Color::Bright(n) => write!(buffer,"{}",(if back {100} else {90})+n%8),
+            basic => write!(buffer,"{}{}",layer,basic.ansi_index().unwrap_or(9))
+        }.unwrap();
+    }
+}
+
+fn distance(a:(u8,u8,u8),b:(u8,u8,u8))->u32{
+    let d = |x:u8,y:u8| (x as i32-y as i32).pow(2) as u32;
+    d(a.0,b.0)+d(a.1,b.1)+d(a.2,b.2)
+}
+fn nearest_ansi(rgb:(u8,u8,u8))->u8{
+    (0..16u8).min_by_key(|&index| distance(rgb, ANSI_RGB[index as usize])).unwrap()
+}
+fn nearest_indexed(rgb:(u8,u8,u8))->u8{
+    let level = |v:u8| (0..6).min_by_key(|&i| (CUBE_LEVELS[i] as i32-v as i32).abs()).unwrap();
+    let (r,g,b) = (level(rgb.0),level(rgb.1),level(rgb.2));
+    let cube = (16+36*r+6*g+b) as u8;
+    let average = (rgb.0 as u32+rgb.1 as u32+rgb.2 as u32)/3;
+    let grey = 232+((average.saturating_sub(8)+5)/10).min(23) as u8;
+    [cube,grey].into_iter().min_by_key(|&index| distance(rgb, Color::Indexed(index).to_rgb().unwrap())).unwrap()
+}
+
+/// How many colors the terminal can show
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum ColorSupport {
+    /// no colors at all,only attributes
+    Mono,
+    /// the 8 basic and 8 bright colors
+    Ansi16,
+    /// the xterm 256 color palette
+    Ansi256,
+    /// any 24-bit color
+    TrueColor,
+}
+impl ColorSupport {
+    /// guess from NO_COLOR,COLORTERM and TERM
+    pub fn detect()->ColorSupport{
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        let term = std::env::var("TERM").unwrap_or_default();
+        if std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) ||
+        ["dumb","vt52","vt100","vt102","vt220"].contains(&term.as_str()){
+            ColorSupport::Mono
+        }else if colorterm == "truecolor" || colorterm == "24bit"{
+            ColorSupport::TrueColor
+        }else if term.contains("256color") || term.contains("direct"){
+            ColorSupport::Ansi256
+        }else {
+            ColorSupport::Ansi16
+        }
+    }
+    pub fn parse(value:&str)->Option<ColorSupport>{
+        match value {
+            "mono" => Some(ColorSupport::Mono),
+            "16" => Some(ColorSupport::Ansi16),
+            "256" => Some(ColorSupport::Ansi256),
+            "truecolor" | "24bit" => Some(ColorSupport::TrueColor),
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falls_back_to_nearest_color(){
+        let orange = Color::Rgb(255,140,0);
+        assert_eq!(orange.fit(ColorSupport::TrueColor),orange);
+        assert_eq!(orange.fit(ColorSupport::Ansi256),Color::Indexed(208));
+        assert_eq!(orange.fit(ColorSupport::Ansi16),Color::Yellow);
+        assert_eq!(Color::Indexed(51).fit(ColorSupport::Ansi16),Color::Bright(6));
+        assert_eq!(Color::Rgb(128,128,128).fit(ColorSupport::Ansi256),Color::Indexed(244));
+        assert_eq!(Color::Red.fit(ColorSupport::Ansi16),Color::Red);
+    }
+    #[test]
+    fn sgr_parameters(){
+        let mut buffer = Vec::new();
+        for (color,back) in [(Color::Red,false),(Color::Bright(2),true),(Color::Indexed(208),false),(Color::Rgb(1,2,3),true),(Color::Default,true)]{
+            color.encode_ascii(&mut buffer, back);
+            buffer.push(b' ');
+        }
+        assert_eq!(String::from_utf8(buffer).unwrap(),"31 102 38;5;208 48;2;1;2;3 49 ");
+    }
+}
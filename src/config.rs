@@ -0,0 +1,223 @@
+use std::path::PathBuf;
+use crate::color::ColorSupport;
+use crate::layout::Panels;
+use crate::profiles::clean_name;
+use crate::rules::{Mode,Randomizer,Rules};
+use crate::theme::Theme;
+
+pub const USAGE:&str = "usage: block_rain [--width N] [--height N] [--hidden N] [--partial-lock-out true|false]
+                  [--line-clear-delay MS] [--entry-delay MS] [--colors auto|mono|16|256|truecolor]
+                  [--theme NAME|FILE] [--ascii auto|true|false] [--ghost true|false]
+                  [--half-blocks true|false] [--cell-size WxH|large]
+                  [--left PANELS] [--right PANELS] [--mode marathon|sprint|endless]
+                  [--speed N] [--randomizer random|bag] [--profile NAME]
+       block_rain replay FILE [options]
+  --width N    columns of the well,4 to 30 (default 10)
+  --height N   visible raws of the well,16 to 40 (default 20)
+  --hidden N   buffer raws above the visible well,0 to 20 (default 4)
+  --partial-lock-out true|false
+               lose when a blocks locks partly above the visible well (default false)
+  --line-clear-delay MS
+               length of the animation wiping full raws,0 to 2000 (default 300)
+  --entry-delay MS
+               wait before the next blocks spawns,0 to 2000 (default 100)
+  --colors auto|mono|16|256|truecolor
+               colors the terminal can show,auto reads NO_COLOR,COLORTERM and TERM (default auto)
+  --theme NAME|FILE
+               classic,blocks,brackets,shade,a NAME.theme file in the themes directory
+               next to the config file or the path of a theme file (default classic)
+  --ascii auto|true|false
+               draw only ASCII characters,auto when the locale is not UTF-8 (default auto)
+  --ghost true|false
+               show where the falling blocks will land (default true)
+  --half-blocks true|false
+               draw two raws of the well in each raw of the terminal,for small panes (default false)
+  --cell-size WxH|large
+               columns 1 to 4 and raws 1 to 3 of a cell,large is 4x2 (default 2x1)
+  --left PANELS --right PANELS
+               comma separated panels of each sidebar,top to bottom,from next,score,speed,
+               lines,timer,stats and danger (default stats and next,danger,score,speed,
+               lines,timer),the least important are hidden when they do not fit
+  --mode marathon|sprint|endless
+               reach 500 points,clear 40 raws or play until the stack tops out (default marathon)
+  --speed N    speed at the start,1 to 30 (default 5)
+  --randomizer random|bag
+               any blocks each time,or every type once per shuffled bag (default random)
+  --profile NAME
+               the player whose lifetime statistics the games count to (default $USER)
+replay plays a recorded game with the well and rules it was played with,the other options apply,
+space pauses,. steps a tick,+ and - change the speed,g goes to a piece and q quits
+the title screen changes mode,speed,randomizer and some settings before each game
+and plays the saved replays
+the same keys can be set as `key = value` lines in $XDG_CONFIG_HOME/block_rain/config";
+
+/// Why the arguments do not give a config
+pub enum ArgsError {
+    /// --help,the usage goes to stdout and the game does not start
+    Help,
+    /// a bad argument or setting,with what was wrong
+    Invalid(String),
+}
+
+/// Settings of a game,read from the config file and then the command line
+#[derive(Clone)]
+pub struct Config{
+    pub width:usize,
+    pub height:usize,
+    pub hidden:usize,
+    pub rules:Rules,
+    /// None to detect it
+    pub colors:Option<ColorSupport>,
+    pub theme:Theme,
+    /// None to detect it from the locale
+    pub ascii:Option<bool>,
+    pub ghost:bool,
+    pub half_blocks:bool,
+    /// columns and raws of a cell
+    pub cell_size:(usize,usize),
+    pub panels:Panels,
+    /// name of the profile the games count to
+    pub profile:String,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Config { width: 10, height: 20, hidden: 4, rules: Rules::default(), colors: None, theme: Theme::default(), ascii: None, ghost: true, half_blocks: false, cell_size: (2,1), panels: Panels::default(),
+            profile: std::env::var("USER").ok().and_then(|user| clean_name(&user)).unwrap_or("player".to_string()) }
+    }
+}
+impl Config {
+    /// defaults,overridden by the config file,overridden by the arguments
+    pub fn load(args:&[String])->Result<Config,ArgsError>{
+        let mut config = Config::default();
+        if let Some(text) = config_path().and_then(|path| std::fs::read_to_string(path).ok()){
+            read_settings(&text, |key,value| config.set(key, value)).map_err(|e| ArgsError::Invalid(format!("config file: {}",e)))?;
+        }
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next(){
+            match arg.strip_prefix("--") {
+                Some("help") => return Err(ArgsError::Help),
+                Some(key) => {
+                    let value = iter.next().ok_or(ArgsError::Invalid(format!("missing value for --{}",key)))?;
+                    config.set(key, value).map_err(ArgsError::Invalid)?;
+                },
+                None => return Err(ArgsError::Invalid(format!("unknown argument: {}\n{}",arg,USAGE)))
+            }
+        }
+        Ok(config)
+    }
+    /// change the setting named key,the names of the config file and of the options
+    pub fn set(&mut self,key:&str,value:&str)->Result<(),String>{
+        let key = key.replace('-', "_");
+        match key.as_str() {
+            "width" => self.width = parse_number(&key, value, 4, 30)?,
+            "height" => self.height = parse_number(&key, value, 16, 40)?,
+            "hidden" => self.hidden = parse_number(&key, value, 0, 20)?,
+            "partial_lock_out" => self.rules.partial_lock_out = parse_bool(&key, value)?,
+            "line_clear_delay" => self.rules.line_clear_delay = parse_number(&key, value, 0, 2000)? as u32,
+            "entry_delay" => self.rules.entry_delay = parse_number(&key, value, 0, 2000)? as u32,
+            "mode" => self.rules.mode = Mode::parse(value).ok_or("mode should be marathon,sprint or endless")?,
+            "speed" => self.rules.start_speed = parse_number(&key, value, 1, 30)? as u32,
+            "randomizer" => self.rules.randomizer = Randomizer::parse(value).ok_or("randomizer should be random or bag")?,
+            "left" => self.panels.left = Panels::parse_list(value)?,
+            "right" => self.panels.right = Panels::parse_list(value)?,
+            "profile" => self.profile = clean_name(value).ok_or("profile should be a name")?,
+            "colors" if value == "auto" => self.colors = None,
+            "theme" => self.theme = Theme::load(value)?,
+            "ascii" if value == "auto" => self.ascii = None,
+            "ascii" => self.ascii = Some(parse_bool(&key, value)?),
+            "ghost" => self.ghost = parse_bool(&key, value)?,
+            "half_blocks" => self.half_blocks = parse_bool(&key, value)?,
+            "cell_size" if value == "large" => self.cell_size = (4,2),
+            "cell_size" => self.cell_size = match value.split_once('x') {
+                Some((columns,raws)) => (parse_number(&key, columns, 1, 4)?,parse_number(&key, raws, 1, 3)?),
+                None => return Err("cell_size should be WxH like 4x2 or large".to_string())
+            },
+            "colors" => self.colors = Some(ColorSupport::parse(value).ok_or("colors should be auto,mono,16,256 or truecolor")?),
+            _ => return Err(format!("unknown setting: {}",key))
+        }
+        Ok(())
+    }
+}
+
+fn parse_number(key:&str,value:&str,min:usize,max:usize)->Result<usize,String>{
+    match value.parse::<usize>() {
+        Ok(number) if number >= min && number <= max => Ok(number),
+        _ => Err(format!("{} should be a number from {} to {}",key,min,max))
+    }
+}
+
+fn parse_bool(key:&str,value:&str)->Result<bool,String>{
+    match value {
+        "true" | "yes" | "on" => Ok(true),
+        "false" | "no" | "off" => Ok(false),
+        _ => Err(format!("{} should be true or false",key))
+    }
+}
+
+/// pass each `key = value` line of text to set,blank lines and lines starting with # are skipped
+pub fn read_settings(text:&str,mut set:impl FnMut(&str,&str)->Result<(),String>)->Result<(),String>{
+    for line in text.lines(){
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#'){
+            continue;
+        }
+        match line.split_once('=') {
+            Some((key,value)) => set(key.trim(), value.trim())?,
+            None => return Err(format!("bad line: {}",line))
+        }
+    }
+    Ok(())
+}
+/// names of the files in dir with the extension,without it,sorted
+pub fn files_with_extension(dir:Option<PathBuf>,extension:&str)->Vec<String>{
+    let mut names:Vec<String> = dir.and_then(|dir| std::fs::read_dir(dir).ok()).map(|entries| {
+        entries.filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != extension{
+                return None;
+            }
+            Some(path.file_stem()?.to_string_lossy().into_owned())
+        }).collect()
+    }).unwrap_or_default();
+    names.sort();
+    names
+}
+
+/// $XDG_CONFIG_HOME/block_rain,or ~/.config/block_rain
+pub fn config_dir()->Option<PathBuf>{
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config")
+    };
+    Some(base.join("block_rain"))
+}
+/// $XDG_DATA_HOME/block_rain,or ~/.local/share/block_rain
+pub fn data_dir()->Option<PathBuf>{
+    let base = match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".local/share")
+    };
+    Some(base.join("block_rain"))
+}
+fn config_path()->Option<PathBuf>{
+    Some(config_dir()?.join("config"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_key_value_lines(){
+        let mut read = Vec::new();
+        let text = "# comment\n\n width = 12 \nspeed=3\n";
+        assert_eq!(read_settings(text, |key,value| {
+            read.push((key.to_string(),value.to_string()));
+            Ok(())
+        }),Ok(()));
+        assert_eq!(read,[("width".to_string(),"12".to_string()),("speed".to_string(),"3".to_string())]);
+        assert_eq!(read_settings("width 12", |_,_| Ok(())),Err("bad line: width 12".to_string()));
+        let mut config = Config::default();
+        assert!(read_settings("width = 2", |key,value| config.set(key, value)).is_err());
+    }
+}
@@ -0,0 +1,147 @@
+/// A box of the sidebar
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum Panel {
+    /// the coming blocks,more of them when there is room
+    Next,
+    Score,
+    Speed,
+    /// raws cleared so far
+    Lines,
+    /// time played,pauses not counted
+    Timer,
+    /// how many blocks of each type were dealt
+    Stats,
+    /// a warning when the stack gets near the top
+    Danger,
+}
+/// the panels from the most to the least important,the last ones are hidden first
+const PRIORITY:[Panel;7] = [Panel::Next,Panel::Score,Panel::Speed,Panel::Danger,Panel::Lines,Panel::Timer,Panel::Stats];
+/// most blocks the next panel shows
+pub const QUEUE_LENGTH:usize = 3;
+
+impl Panel {
+    pub fn name(&self)->&'static str{
+        match self {
+            Panel::Next => "next",
+            Panel::Score => "score",
+            Panel::Speed => "speed",
+            Panel::Lines => "lines",
+            Panel::Timer => "timer",
+            Panel::Stats => "stats",
+            Panel::Danger => "danger",
+        }
+    }
+    fn parse(name:&str)->Option<Panel>{
+        PRIORITY.into_iter().find(|panel| panel.name() == name)
+    }
+    fn priority(&self)->usize{
+        PRIORITY.iter().position(|panel| panel == self).unwrap()
+    }
+    /// raws the panel needs,preview is the raws of one blocks preview
+    fn height(&self,preview:usize)->usize{
+        match self {
+            Panel::Next => 1+preview,
+            Panel::Stats => 7,
+            Panel::Danger => 1,
+            _ => 2
+        }
+    }
+}
+
+/// A panel put in a sidebar
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub struct Placed {
+    pub panel:Panel,
+    /// in the sidebar left of the well,else right of it
+    pub left:bool,
+    /// first raw inside the frame
+    pub raw:usize,
+    pub height:usize,
+}
+
+/// Which panels go on which side of the well,top to bottom
+#[derive(Clone)]
+pub struct Panels {
+    pub left:Vec<Panel>,
+    pub right:Vec<Panel>,
+}
+impl Default for Panels {
+    fn default() -> Self {
+        Panels {
+            left: vec![Panel::Stats],
+            right: vec![Panel::Next,Panel::Danger,Panel::Score,Panel::Speed,Panel::Lines,Panel::Timer],
+        }
+    }
+}
+impl Panels {
+    /// a comma separated list of panel names,empty for none
+    pub fn parse_list(value:&str)->Result<Vec<Panel>,String>{
+        value.split(',').map(str::trim).filter(|name| !name.is_empty()).map(|name| {
+            Panel::parse(name).ok_or(format!("unknown panel {},panels are next,score,speed,lines,timer,stats and danger",name))
+        }).collect()
+    }
+    /// Put the panels in sidebars of raws raws,starting at raw 1 below the top of the frame.
+    /// Without two sides the left panels go under the right ones.
+    /// Panels that do not fit are dropped from the least important,
+    /// the raws left over go to gaps between the panels and then to more next blocks.
+    pub fn place(&self,raws:usize,two_sides:bool,preview:usize)->Vec<Placed>{
+        let sides:Vec<(bool,Vec<Panel>)> = if two_sides{
+            vec![(true,self.left.clone()),(false,self.right.clone())]
+        }else {
+            vec![(false,self.right.iter().chain(&self.left).copied().collect())]
+        };
+        let mut placed = Vec::new();
+        for (left,panels) in sides{
+            let mut by_priority = panels.clone();
+            by_priority.sort_by_key(Panel::priority);
+            let mut used = 0;
+            let kept:Vec<Panel> = by_priority.into_iter().filter(|panel| {
+                let fits = used+panel.height(preview) <= raws;
+                if fits{
+                    used += panel.height(preview);
+                }
+                fits
+            }).collect();
+            let count = kept.len();
+            let gap = if count > 1 && raws-used >= count-1 {1} else {0};
+            let mut spare = raws-used-gap*count.saturating_sub(1);
+            let mut raw = 1;
+            for panel in panels.into_iter().filter(|panel| kept.contains(panel)){
+                let mut height = panel.height(preview);
+                if panel == Panel::Next{
+                    let extra = (spare/preview).min(QUEUE_LENGTH-1);
+                    height += extra*preview;
+                    spare -= extra*preview;
+                }
+                placed.push(Placed { panel, left, raw, height });
+                raw += height+gap;
+            }
+        }
+        placed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hides_the_least_important_panels_first(){
+        let panels = Panels::default();
+        let placed = panels.place(20, true, 4);
+        assert_eq!(placed.iter().filter(|p| p.left).count(),1);
+        let next = placed.iter().find(|p| p.panel == Panel::Next).unwrap();
+        assert_eq!((next.raw,next.height),(1,5));
+        // on one side in 10 raws only the most important panels are left,without gaps
+        let placed = panels.place(10, false, 2);
+        let names:Vec<&str> = placed.iter().map(|p| p.panel.name()).collect();
+        assert_eq!(names,["next","danger","score","speed","lines"]);
+        assert_eq!(placed.last().map(|p| p.raw+p.height),Some(11));
+        // spare raws show more of the queue
+        let placed = Panels { left: vec![], right: vec![Panel::Next,Panel::Score] }.place(20, true, 4);
+        assert_eq!(placed[0].height,13);
+        assert_eq!(placed[1].raw,15);
+        assert!(Panels::parse_list("next, score,").is_ok());
+        assert!(Panels::parse_list("next,hold").is_err());
+    }
+}
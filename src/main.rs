@@ -1,116 +1,158 @@
 #![allow(dead_code)]
-use core::panic;
 use std::{io::Write, sync::mpsc::Receiver};
 use pcg_with_xorshift::{PcgWithXorshift, RandomNumberGeneratorEngine};
 use raw_terminal::*;
+use color::{Color,ColorSupport};
+use theme::Theme;
+mod color;
+mod config;
+mod layout;
+mod menu;
+mod profiles;
+mod replay;
+mod scores;
+mod rules;
+mod terminal;
+mod theme;
 
-#[derive(Clone,Copy)]
-enum FrontColor {
-    Default,
-    White,
-    Black,
-    Red,
-    Green,
-    Yellow,
-    Blue,
-    Purple,
-    Cyan
-}
-impl FrontColor {
-    fn encode_ascii(&self,buffer:&mut [u8])->usize{
-        let color_codes = match self {
-            // 31 => 0x33,0x31 is two character
-            FrontColor::Default => &[0x33u8,0x39u8],
-            FrontColor::Black   => &[0x33u8,0x30u8],
-            FrontColor::Blue    => &[0x33u8,0x34u8],
-            FrontColor::Cyan    => &[0x33u8,0x36u8],
-            FrontColor::Green   => &[0x33u8,0x32u8],
-            FrontColor::Purple  => &[0x33u8,0x35u8],
-            FrontColor::Red     => &[0x33u8,0x31u8],
-            FrontColor::White   => &[0x33u8,0x37u8],
-            FrontColor::Yellow  => &[0x33u8,0x33u8],
-        };
-        buffer[0] = 0x1bu8;//ESC
-        buffer[1] = 0x5bu8;//[
-        buffer[2] = color_codes[0];
-        buffer[3] = color_codes[1];
-        4
+/// text attributes of a pixel,a set of bits
+#[derive(Clone,Copy,PartialEq,Default,Debug)]
+struct Attributes(u8);
+impl Attributes {
+    const BOLD:Attributes = Attributes(1);
+    const DIM:Attributes = Attributes(1<<1);
+    const UNDERLINE:Attributes = Attributes(1<<2);
+    const BLINK:Attributes = Attributes(1<<3);
+    const REVERSE:Attributes = Attributes(1<<4);
+    /// bit,SGR parameter to turn it on and to turn it off
+    const CODES:[(Attributes,u8,u8);5] = [(Attributes::BOLD,1,22),(Attributes::DIM,2,22),(Attributes::UNDERLINE,4,24),(Attributes::BLINK,5,25),(Attributes::REVERSE,7,27)];
+    fn contains(&self,other:Attributes)->bool{
+        self.0 & other.0 == other.0
     }
-}
-#[derive(Clone,Copy)]
-enum BackColor {
-    Default,
-    White,
-    Black,
-    Red,
-    Green,
-    Yellow,
-    Blue,
-    Purple,
-    Cyan
-}
-impl BackColor {
-    fn encode_ascii(&self,buffer:&mut [u8])->usize{
-        let color_codes = match self {
-            BackColor::Default => &[0x34u8,0x39u8],
-            BackColor::Black   => &[0x34u8,0x30u8],
-            BackColor::Blue    => &[0x34u8,0x34u8],
-            BackColor::Cyan    => &[0x34u8,0x36u8],
-            BackColor::Green   => &[0x34u8,0x32u8],
-            BackColor::Purple  => &[0x34u8,0x35u8],
-            BackColor::Red     => &[0x34u8,0x31u8],
-            BackColor::White   => &[0x34u8,0x37u8],
-            BackColor::Yellow  => &[0x34u8,0x33u8],
-        };
-        buffer[0] = b';';
-        buffer[1] = color_codes[0];
-        buffer[2] = color_codes[1];
-        buffer[3] = 0x6du8;//m
-        4
+    fn with(self,other:Attributes)->Attributes{
+        Attributes(self.0 | other.0)
     }
-    fn is_default(&self)->bool{
-        matches!(self, BackColor::Default)
+    /// push the SGR parameters that turn the from attributes into these
+    fn encode_ascii(&self,from:Attributes,buffer:&mut Vec<u8>,open:usize){
+        // bold and dim are turned off by the same parameter,turn both off and on again what stays
+        let intensity = Attributes::BOLD.with(Attributes::DIM);
+        let from = if (from.0 & !self.0) & intensity.0 != 0 {
+            push_parameter(22, buffer, open);
+            Attributes(from.0 & !intensity.0)
+        }else {
+            from
+        };
+        for (attribute,on,off) in Attributes::CODES{
+            if self.contains(attribute) != from.contains(attribute){
+                push_parameter(if self.contains(attribute) {on} else {off}, buffer, open);
+            }
+        }
     }
 }
-#[derive(Clone,Copy)]
+/// the colors and attributes the terminal currently writes with
+#[derive(Clone,Copy,PartialEq)]
+struct Brush{
+    front_color:Color,
+    back_color:Color,
+    attributes:Attributes,
+}
+#[derive(Clone,Copy,PartialEq)]
 struct Pixel{
-    front_color:FrontColor,
-    back_color:BackColor,
+    front_color:Color,
+    back_color:Color,
+    attributes:Attributes,
     character:char,
 }
 impl Default for Pixel {
     fn default() -> Self {
-        Pixel { front_color: FrontColor::Default, back_color: BackColor::Default, character: ' ' }
+        Pixel { front_color: Color::Default, back_color: Color::Default, attributes: Attributes::default(), character: ' ' }
     }
 }
 impl Pixel {
-    fn new(c:char,fc:FrontColor,bc:BackColor)->Pixel{
-        Pixel { front_color: fc, back_color: bc, character: c }
+    fn new(c:char,fc:Color,bc:Color)->Pixel{
+        Pixel { front_color: fc, back_color: bc, attributes: Attributes::default(), character: c }
+    }
+    fn with_attributes(mut self,attributes:Attributes)->Pixel{
+        self.attributes = self.attributes.with(attributes);
+        self
     }
-    fn encode_ascii(&self,buffer:&mut [u8])->usize{
-        let mut count = 0;
-        count+=self.front_color.encode_ascii(&mut buffer[count..]);
-        count+=self.back_color.encode_ascii(&mut buffer[count..]);
-        count+=push_char_into_array(self.character, &mut buffer[count..]);
-        count
+    /// push the pixel,with a SGR sequence only for what differs from the brush
+    /// brush is None when the terminal state is unknown
+    fn encode_ascii(&self,buffer:&mut Vec<u8>,brush:&mut Option<Brush>){
+        let start = buffer.len();
+        buffer.extend_from_slice(b"\x1b[");
+        let open = buffer.len();
+        let from = match brush {
+            Some(current) => *current,
+            None => {
+                push_parameter(0, buffer, open);
+                Brush { front_color: Color::Default, back_color: Color::Default, attributes: Attributes::default() }
+            }
+        };
+        self.attributes.encode_ascii(from.attributes, buffer, open);
+        if from.front_color != self.front_color{
+            push_parameter_separator(buffer, open);
+            self.front_color.encode_ascii(buffer,false);
+        }
+        if from.back_color != self.back_color{
+            push_parameter_separator(buffer, open);
+            self.back_color.encode_ascii(buffer,true);
+        }
+        if buffer.len() == open{
+            buffer.truncate(start);
+        }else {
+            buffer.push(b'm');
+            *brush = Some(Brush { front_color: self.front_color, back_color: self.back_color, attributes: self.attributes });
+        }
+        push_char(self.character, buffer);
+    }
+    /// the same pixel in colors the terminal can show,
+    /// without colors a background turns into reverse video
+    fn fit(self,support:ColorSupport)->Pixel{
+        let pixel = Pixel { front_color: self.front_color.fit(support), back_color: self.back_color.fit(support), ..self };
+        if support != ColorSupport::Mono{
+            return pixel;
+        }
+        // only bold and reverse,blinking turns into reverse and dim into normal weight,
+        // which still sets a ghost apart from the bold pieces
+        let mut attributes = Attributes(self.attributes.0 & Attributes::BOLD.with(Attributes::REVERSE).0);
+        if self.attributes.contains(Attributes::BLINK) || !self.back_color.is_default(){
+            attributes = attributes.with(Attributes::REVERSE);
+        }
+        Pixel { attributes, ..pixel }
     }
     fn change_character(&mut self,new_c:char){
         self.character = new_c;
     }
-    fn change_front_color(&mut self,new_fc:FrontColor){
+    fn change_front_color(&mut self,new_fc:Color){
         self.front_color = new_fc;
     }
-    fn change_back_color(&mut self,new_bc:BackColor){
+    fn change_back_color(&mut self,new_bc:Color){
         self.back_color = new_bc;
     }
-    fn change_all(&mut self,new_c:char,new_fc:FrontColor,new_bc:BackColor){
+    fn change_all(&mut self,new_c:char,new_fc:Color,new_bc:Color){
         self.change_character(new_c);
         self.change_front_color(new_fc);
         self.change_back_color(new_bc);
     }
 }
-fn push_char_into_array(c:char,buffer:&mut [u8])->usize{
-    c.encode_utf8(buffer).len()
+/// separate the next SGR parameter from the ones pushed after open
+fn push_parameter_separator(buffer:&mut Vec<u8>,open:usize){
+    if buffer.len() > open{
+        buffer.push(b';');
+    }
+}
+fn push_parameter(parameter:u8,buffer:&mut Vec<u8>,open:usize){
+    push_parameter_separator(buffer, open);
+    write!(buffer,"{}",parameter).unwrap();
+}
+fn push_char(c:char,buffer:&mut Vec<u8>){
+    let mut bytes = [0u8;4];
+    buffer.extend_from_slice(c.encode_utf8(&mut bytes).as_bytes());
+}
+/// move the cursor to column x of raw y,both counted from 0
+fn push_cursor(x:usize,y:usize,buffer:&mut Vec<u8>){
+    write!(buffer,"\x1b[{};{}H",y+1,x+1).unwrap();
 }
 #[derive(Clone, Copy)]
 enum BlockType{
@@ -132,14 +174,14 @@ impl BlockType {
             BlockType::Ttype => (0,1),
         }
     }
-    fn get_color(&self)->BackColor{
+    fn get_color(&self)->Color{
         match self {
-            BlockType::Itype => BackColor::Blue,
-            BlockType::Ltype => BackColor::Cyan,
-            BlockType::Otype => BackColor::Green,
-            BlockType::Stype => BackColor::Purple,
-            BlockType::Ztype => BackColor::Red,
-            BlockType::Ttype => BackColor::Yellow,
+            BlockType::Itype => Color::Rgb(0,215,255),
+            BlockType::Ltype => Color::Rgb(255,140,0),
+            BlockType::Otype => Color::Rgb(255,230,0),
+            BlockType::Stype => Color::Rgb(0,200,60),
+            BlockType::Ztype => Color::Rgb(220,20,40),
+            BlockType::Ttype => Color::Rgb(160,40,220),
         }
     }
     fn random_type(rand:u32)->BlockType{
@@ -153,6 +195,7 @@ impl BlockType {
         }
     }
 }
+#[derive(Clone)]
 struct Blocks{
     square:Vec<Pixel>,
     t:BlockType,
@@ -214,7 +257,7 @@ impl Blocks {
                                 pixel.change_back_color(self.t.get_color());
                             }
                             else{
-                                pixel.change_back_color(BackColor::Default);
+                                pixel.change_back_color(Color::Default);
                             }
                         }
                         self.inner_left_margin = 0;
@@ -227,7 +270,7 @@ impl Blocks {
                                 pixel.change_back_color(self.t.get_color());
                             }
                             else{
-                                pixel.change_back_color(BackColor::Default);
+                                pixel.change_back_color(Color::Default);
                             }
                         }
                         self.state = 0;
@@ -246,7 +289,7 @@ impl Blocks {
                                 pixel.change_back_color(self.t.get_color());
                             }
                             else{
-                                pixel.change_back_color(BackColor::Default);
+                                pixel.change_back_color(Color::Default);
                             }
                         }
                         self.inner_left_margin = align;
@@ -260,7 +303,7 @@ impl Blocks {
                                 pixel.change_back_color(self.t.get_color());
                             }
                             else{
-                                pixel.change_back_color(BackColor::Default);
+                                pixel.change_back_color(Color::Default);
                             }
                         }
                         self.inner_left_margin = align;
@@ -274,7 +317,7 @@ impl Blocks {
                                 pixel.change_back_color(self.t.get_color());
                             }
                             else{
-                                pixel.change_back_color(BackColor::Default);
+                                pixel.change_back_color(Color::Default);
                             }
                         }
                         self.inner_left_margin = align;
@@ -288,7 +331,7 @@ impl Blocks {
                                 pixel.change_back_color(self.t.get_color());
                             }
                             else{
-                                pixel.change_back_color(BackColor::Default);
+                                pixel.change_back_color(Color::Default);
                             }
                         }
                         self.inner_left_margin = align;
@@ -306,7 +349,7 @@ impl Blocks {
                                 pixel.change_back_color(self.t.get_color());
                             }
                             else{
-                                pixel.change_back_color(BackColor::Default);
+                                pixel.change_back_color(Color::Default);
                             }
                         }
                         self.inner_left_margin = align;
@@ -320,7 +363,7 @@ impl Blocks {
                                 pixel.change_back_color(self.t.get_color());
                             }
                             else{
-                                pixel.change_back_color(BackColor::Default);
+                                pixel.change_back_color(Color::Default);
                             }
                         }
                         self.inner_left_margin = align;
@@ -338,7 +381,7 @@ impl Blocks {
                                 pixel.change_back_color(self.t.get_color());
                             }
                             else{
-                                pixel.change_back_color(BackColor::Default);
+                                pixel.change_back_color(Color::Default);
                             }
                         }
                         self.inner_left_margin = align;
@@ -352,7 +395,7 @@ impl Blocks {
                                 pixel.change_back_color(self.t.get_color());
                             }
                             else{
-                                pixel.change_back_color(BackColor::Default);
+                                pixel.change_back_color(Color::Default);
                             }
                         }
                         self.inner_left_margin = align;
@@ -370,7 +413,7 @@ impl Blocks {
                                     pixel.change_back_color(self.t.get_color());
                                 }
                                 else{
-                                    pixel.change_back_color(BackColor::Default);
+                                    pixel.change_back_color(Color::Default);
                                 }
                             }
                             self.inner_left_margin = align;
@@ -384,7 +427,7 @@ impl Blocks {
                                     pixel.change_back_color(self.t.get_color());
                                 }
                                 else{
-                                    pixel.change_back_color(BackColor::Default);
+                                    pixel.change_back_color(Color::Default);
                                 }
                             }
                             self.inner_left_margin = align;
@@ -398,7 +441,7 @@ impl Blocks {
                                     pixel.change_back_color(self.t.get_color());
                                 }
                                 else{
-                                    pixel.change_back_color(BackColor::Default);
+                                    pixel.change_back_color(Color::Default);
                                 }
                             }
                             self.inner_left_margin = align;
@@ -412,7 +455,7 @@ impl Blocks {
                                     pixel.change_back_color(self.t.get_color());
                                 }
                                 else{
-                                    pixel.change_back_color(BackColor::Default);
+                                    pixel.change_back_color(Color::Default);
                                 }
                             }
                             self.inner_left_margin = align;
@@ -423,440 +466,1293 @@ impl Blocks {
                 }
         }
     }
-    fn left_and_right_move(&mut self,go_left:bool){
-        if go_left{
-            if self.inner_left_margin>0{
-                for y in 0..4{
-                    for x in 0..3{
-                        self.square.as_mut_slice()[y*4+x] = self.square[y*4+x+1];
+}
+#[derive(Clone, Copy, PartialEq)]
+enum Key{
+    Left,
+    Right,
+    Up,
+    Down,
+    /// enter or space on the title screen
+    Select,
+    Pause,
+    Quit,
+    Theme,
+    Yes,
+    No,
+    Other,
+    Nothing,
+    /// stdin reached its end or failed,no more keys will come
+    Closed,
+}
+/// why a game was lost,named after the guideline terms
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[allow(clippy::enum_variant_names)]
+enum TopOut{
+    /// the new blocks overlaps the stack where it spawns
+    BlockOut,
+    /// the blocks locked entirely above the visible well
+    LockOut,
+    /// part of the blocks locked above the visible well,with the partial lock out rule
+    /// or when there are no hidden raws to keep that part in
+    PartialLockOut,
+}
+impl TopOut {
+    fn describe(&self)->&'static str{
+        match self {
+            TopOut::BlockOut => "block out,no room for the next blocks",
+            TopOut::LockOut => "lock out,the blocks locked above the well",
+            TopOut::PartialLockOut => "partial lock out,the blocks locked partly above the well",
+        }
+    }
+}
+/// length of one turn of the game loop in milliseconds
+const TICK:u32 = 50;
+/// what happens between one blocks locking and the next one spawning
+#[derive(Clone, Copy, PartialEq)]
+enum Phase{
+    /// a blocks is falling
+    Falling,
+    /// the full raws are being wiped,milliseconds left
+    Clearing(u32),
+    /// waiting for the next blocks,milliseconds left
+    Entry(u32),
+}
+enum Outcome{
+    Win,
+    TopOut(TopOut),
+    Quit,
+}
+/// Picks the types of the coming blocks
+struct Dealer{
+    pcg:PcgWithXorshift,
+    randomizer:rules::Randomizer,
+    /// what is left of the bag,taken from the end
+    bag:Vec<BlockType>,
+}
+impl Dealer {
+    fn new(pcg:PcgWithXorshift,randomizer:rules::Randomizer)->Dealer{
+        Dealer { pcg, randomizer, bag: Vec::new() }
+    }
+    fn deal(&mut self)->BlockType{
+        match self.randomizer {
+            rules::Randomizer::Random => BlockType::random_type(self.pcg.get_round(6)),
+            rules::Randomizer::Bag => {
+                if self.bag.is_empty(){
+                    self.bag = (0..6).map(BlockType::random_type).collect();
+                    // Fisher-Yates
+                    for i in (1..6).rev(){
+                        let j = self.pcg.get_round(i as u32+1) as usize;
+                        self.bag.swap(i, j);
                     }
-                    self.square.as_mut_slice()[y*4+3] = Pixel::default();
-                }
-                self.inner_left_margin-=1;
-                self.inner_right_margin+=1;
-            }
-        }else if self.inner_right_margin>0{
-            for y in 0..4{
-                for x in (1..4).rev(){
-                    self.square.as_mut_slice()[y*4+x] = self.square[y*4+x-1];
                 }
-                self.square.as_mut_slice()[y*4] = Pixel::default();
+                self.bag.pop().unwrap()
             }
-            self.inner_right_margin-=1;
-            self.inner_left_margin+=1;
         }
     }
 }
-struct Game{
-    key_reader:Receiver<u8>,
+struct Game<'a>{
+    key_reader:&'a Receiver<u8>,
     game_board:Board,
     current_block_type:BlockType,
-    next_block_type:BlockType,
-    pcg:PcgWithXorshift,
+    /// the coming blocks,first the next one
+    queue:std::collections::VecDeque<BlockType>,
+    /// the dealer starts from it,the same seed deals the same blocks
+    seed:u64,
+    dealer:Dealer,
+    rules:rules::Rules,
+    /// blocks locked so far
+    pieces:u32,
+    score:u32,
+    speed:u32,
+    lines:u32,
+    /// milliseconds played,pauses not counted
+    time:u32,
+    stats:[u32;6],
+    /// clears of four raws at once
+    tetrises:u32,
+    t_spins:u32,
+    /// the last move of the falling blocks was a turn
+    rotated:bool,
+    /// the keys of the game so far
+    replay:replay::Replay,
+    phase:Phase,
+    /// the blocks falls one raw when it runs down to the speed,it goes down by the speed each tick
+    fall:u32,
+    /// the board changed since it was last drawn
+    redraw:bool,
+    /// moves read while the blocks could not take them,played one a tick once it falls
+    pending:std::collections::VecDeque<Key>,
 }
-impl Game {
-    fn new(reader:Receiver<u8>)->Game{
-        let dimensions = get_terminal_dimensions().unwrap();
-        if dimensions.0 < 17||dimensions.1<31{
-            panic!("terminal dimensions too small!");
-        }
-        let real_dimensions = format_dimensions(dimensions);
-        let mut pwxs = PcgWithXorshift::new(None);
-        let ct = BlockType::random_type(pwxs.get_round(6));
-        let nt = BlockType::random_type(pwxs.get_round(6));
-        Game { key_reader: reader, game_board: Board::new(real_dimensions,ct), current_block_type: ct, next_block_type:nt ,pcg:pwxs}
-    }
-    fn run(&mut self){
-        
-        self.game_board.init();
-        self.game_board.draw_next_block(self.next_block_type);
-        self.game_board.draw_score(0);
-        self.game_board.draw_speed(5);
-        let mut score:u32 = 0;//max 500
-        let mut speed:u32 = 5;
-        let mut total_time = 100;
-        let mut highest_raw = self.game_board.raws-1;
-        loop {
-            let mut flag = false;
-            if total_time <= speed{
-                self.game_board.blocks_position.1+=1;
-                total_time = 100;
-                flag=true;
+impl<'a> Game<'a> {
+    /// a game on the terminal,which has to be big enough for it
+    fn new(reader:&'a Receiver<u8>,config:&config::Config,seed:u64)->Result<Game<'a>,terminal::StartError>{
+        let dimensions = get_terminal_dimensions().map_err(|_| terminal::StartError::NoTty)?;
+        let game = Game::with_dimensions(reader, config, seed, dimensions);
+        if game.game_board.too_small{
+            return Err(terminal::StartError::TooSmall { needed: game.game_board.frame_size(), got: dimensions });
+        }
+        Ok(game)
+    }
+    /// a game on a terminal of dimensions,(raws,columns),its board says so when they are too small
+    fn with_dimensions(reader:&'a Receiver<u8>,config:&config::Config,seed:u64,dimensions:(u16,u16))->Game<'a>{
+        let mut dealer = Dealer::new(PcgWithXorshift::new(Some(seed)), config.rules.randomizer);
+        let ct = dealer.deal();
+        let queue:std::collections::VecDeque<BlockType> = (0..layout::QUEUE_LENGTH).map(|_| dealer.deal()).collect();
+        let mut board = Board::from_config(config, dimensions, ct, queue.iter().copied().collect());
+        let mut stats = [0;6];
+        stats[ct as usize] = 1;
+        board.draw_stats(stats);
+        board.draw_speed(config.rules.start_speed);
+        Game { key_reader: reader, game_board: board, current_block_type: ct, queue, seed, dealer, rules:config.rules.clone(),
+            pieces:0,score:0,speed:config.rules.start_speed,lines:0,time:0,stats,tetrises:0,t_spins:0,rotated:false,
+            replay:replay::Replay::new(config, seed), phase:Phase::Falling, fall:100, redraw:true,
+            pending:std::collections::VecDeque::new() }
+    }
+    fn run(&mut self)->Outcome{
+        let mut paused = false;
+        let mut quitting = false;
+        let outcome = loop {
+            if terminal::signalled().is_some(){
+                break Outcome::Quit;
             }
-            else {
-                total_time-=speed;
-                let key = self.get_key_input_from_stdin();
-                if key < 4{
-                    self.game_board.mov(key);
-                    flag = true;
+            if terminal::take_resized(){
+                if let Ok(dimensions) = get_terminal_dimensions(){
+                    self.game_board.layout(dimensions);
+                    self.game_board.draw();
                 }
             }
-            if self.game_board.is_bottom(){
-                for y in self.game_board.blocks_position.1.saturating_sub(4)..self.game_board.blocks_position.1{
-                    for x in 0..4usize{
-                        if !self.game_board.blocks.square[(self.game_board.blocks_position.1-y-1)*4+x].back_color.is_default(){
-                            if  y < highest_raw{
-                                highest_raw = y;
-                            }
-                            self.game_board.matrix[(self.game_board.blocks_position.1-(y-self.game_board.blocks_position.1.saturating_sub(4)))*self.game_board.columns+(self.game_board.blocks_position.0+x)*2+2].change_back_color(self.current_block_type.get_color());
-                            self.game_board.matrix[(self.game_board.blocks_position.1-(y-self.game_board.blocks_position.1.saturating_sub(4)))*self.game_board.columns+(self.game_board.blocks_position.0+x)*2+1].change_back_color(self.current_block_type.get_color());
+            if paused || quitting || self.game_board.too_small{
+                // timers are frozen until the player resumes or answers the quit prompt
+                match self.get_key_input_from_stdin() {
+                    Key::Pause if !quitting => {
+                        paused = false;
+                        self.game_board.banner = None;
+                        self.game_board.draw();
+                    },
+                    Key::Theme if paused && !quitting => {
+                        let themes = Theme::available();
+                        let current = themes.iter().position(|name| *name == self.game_board.theme.name);
+                        let next = &themes[current.map_or(0, |index| (index+1)%themes.len())];
+                        match Theme::load(next) {
+                            Ok(theme) => {
+                                self.game_board.set_theme(theme);
+                                self.game_board.banner = Some(self.pause_banner());
+                            },
+                            Err(e) => self.game_board.banner = Some(e)
                         }
+                        self.game_board.draw();
+                    },
+                    Key::Quit if !quitting => {
+                        quitting = true;
+                        self.game_board.banner = Some("quit? y/n".to_string());
+                        self.game_board.draw();
+                    },
+                    Key::Yes if quitting => break Outcome::Quit,
+                    // nobody can answer the prompt any more
+                    Key::Closed => break Outcome::Quit,
+                    Key::Nothing => {},
+                    _ if quitting => {
+                        quitting = false;
+                        self.game_board.banner = if paused {Some(self.pause_banner())} else {None};
+                        self.game_board.draw();
+                    },
+                    _ => {}
+                }
+                std::thread::sleep(std::time::Duration::from_millis(TICK as u64));
+                continue;
+            }
+            let key = self.get_key_input_from_stdin();
+            // a tick is a pass here,time only goes on with them
+            self.replay.record(self.time/TICK, key);
+            match key {
+                Key::Pause => {
+                    paused = true;
+                    self.game_board.banner = Some(self.pause_banner());
+                    self.redraw = true;
+                },
+                Key::Quit => {
+                    quitting = true;
+                    self.game_board.banner = Some("quit? y/n".to_string());
+                    self.redraw = true;
+                },
+                Key::Closed => break Outcome::Quit,
+                _ => {}
+            }
+            if let Some(outcome) = self.step(key){
+                break outcome;
+            }
+            if self.redraw{
+                self.game_board.draw();
+                self.redraw = false;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(TICK as u64));
+        };
+        self.replay.finish(self.time/TICK, !matches!(outcome,Outcome::Quit));
+        outcome
+    }
+    /// keep a move until the falling blocks can take it
+    fn press(&mut self,key:Key){
+        if matches!(key,Key::Left|Key::Right|Key::Up|Key::Down){
+            self.pending.push_back(key);
+        }
+    }
+    /// play one tick with the key read in it,Some when the game ends
+    fn step(&mut self,key:Key)->Option<Outcome>{
+        self.press(key);
+        match self.phase {
+            Phase::Falling => {
+                // the move goes first so a key pressed on a tick the blocks falls is not lost
+                if let Some(key) = self.pending.pop_front(){
+                    let direction = match key {
+                        Key::Left => Some(0),
+                        Key::Right => Some(1),
+                        Key::Up => Some(2),
+                        Key::Down => Some(3),
+                        _ => None
+                    };
+                    if let Some(direction) = direction.filter(|direction| self.game_board.mov(*direction)){
+                        self.rotated = direction == 2;
                     }
+                    self.redraw = true;
                 }
-                score += self.game_board.remove_line()*10;
-                if score >=500{
-                    break;
+                if self.fall <= self.speed{
+                    if self.game_board.try_move(0, 1){
+                        self.rotated = false;
+                    }
+                    self.fall = 100;
+                    self.redraw = true;
                 }
-                if highest_raw <=1{
-                    break;
+                else {
+                    self.fall -= self.speed;
                 }
-                speed = 5+score/100;
-                self.current_block_type = self.next_block_type;
-                self.game_board.blocks = Blocks::new(self.current_block_type);
-                self.next_block_type = BlockType::random_type(self.pcg.get_round(6));
-                self.game_board.blocks_position.0 = ((self.game_board.columns-10)/2-4)/2;
-                self.game_board.blocks_position.1 = 1;
-
-                self.game_board.draw_next_block(self.next_block_type);
-                self.game_board.draw_score(score);
-                self.game_board.draw_speed(speed);
-                flag = true;
+                if self.game_board.is_bottom(){
+                    if self.rotated && matches!(self.current_block_type,BlockType::Ttype) && self.game_board.t_corners(){
+                        self.t_spins += 1;
+                    }
+                    let (top,bottom) = self.game_board.lock(self.current_block_type);
+                    self.pieces += 1;
+                    if let Some(reason) = self.game_board.lock_out(top, bottom, self.rules.partial_lock_out){
+                        return Some(Outcome::TopOut(reason));
+                    }
+                    self.game_board.show_blocks = false;
+                    self.game_board.clearing = self.game_board.full_lines();
+                    self.game_board.wipe = 0;
+                    self.phase = if self.game_board.clearing.is_empty() {Phase::Entry(self.rules.entry_delay)} else {Phase::Clearing(self.rules.line_clear_delay)};
+                    self.redraw = true;
+                }
+            },
+            Phase::Clearing(left) => {
+                self.phase = Phase::Clearing(left.saturating_sub(TICK));
+                let done = (self.rules.line_clear_delay-left.saturating_sub(TICK)) as usize;
+                self.game_board.wipe = (self.game_board.width.div_ceil(2)*done).div_ceil(self.rules.line_clear_delay as usize);
+                self.redraw = true;
+            },
+            Phase::Entry(left) => self.phase = Phase::Entry(left.saturating_sub(TICK))
+        }
+        if self.phase == Phase::Clearing(0){
+            self.game_board.clearing.clear();
+            let removed = self.game_board.remove_line();
+            if removed == 4{
+                self.tetrises += 1;
             }
-            if flag{
-                self.game_board.draw();
+            self.score += removed*10;
+            self.lines += removed;
+            self.game_board.draw_lines(self.lines);
+            if self.rules.mode.won(self.score, self.lines){
+                return Some(Outcome::Win);
             }
-            std::thread::sleep(std::time::Duration::from_millis(50));
+            self.speed = self.rules.start_speed+self.score/100;
+            self.game_board.draw_score(self.score);
+            self.game_board.draw_speed(self.speed);
+            self.phase = Phase::Entry(self.rules.entry_delay);
+            self.redraw = true;
         }
-        reset();
-        hide_cursor(false);
-        set_mode(true);
-        if score>=500{
-            println!("You win");
-        }else {
-            println!("You lose");
-        }
-    }
-    fn get_key_input_from_stdin(&self)->u8{
-        if let Some(byte) = self.key_reader.try_iter().next(){
-            match byte {
-                b'a' => 0,
-                b'd' => 1,
-                b'w' => 2,
-                b's' => 3,
-                _ => 4
+        if self.phase == Phase::Entry(0){
+            if !self.spawn(){
+                return Some(Outcome::TopOut(TopOut::BlockOut));
             }
-        }else {
-            4
+            self.phase = Phase::Falling;
+            self.fall = 100;
+            self.redraw = true;
         }
+        self.time += TICK;
+        self.redraw |= self.game_board.draw_time(self.time);
+        None
+    }
+    /// what the results screen and the scrollback show of a finished game
+    fn results(&self,outcome:Outcome)->menu::Results{
+        let (title,reason) = match outcome {
+            Outcome::Win => ("You win",""),
+            Outcome::TopOut(reason) => ("You lose",reason.describe()),
+            Outcome::Quit => ("Quit",""),
+        };
+        menu::Results { title, reason, score: self.score, lines: self.lines, speed: self.speed, time: self.time, pieces: self.pieces,
+            tetrises: self.tetrises, t_spins: self.t_spins, record: None }
+    }
+    /// bring in the next blocks,false if it does not fit
+    fn spawn(&mut self)->bool{
+        let t = self.queue.pop_front().unwrap();
+        self.queue.push_back(self.dealer.deal());
+        self.game_board.draw_queue(self.queue.iter().copied().collect());
+        self.stats[t as usize] += 1;
+        self.game_board.draw_stats(self.stats);
+        self.enter(t)
+    }
+    /// put the blocks of type t at the top of the well,false if it does not fit
+    fn enter(&mut self,t:BlockType)->bool{
+        self.current_block_type = t;
+        self.rotated = false;
+        self.game_board.enter(t)
+    }
+    /// the pause screen doubles as the settings menu
+    fn pause_banner(&self)->String{
+        format!("paused\n\np resume\nq quit\nt theme:\n{}",self.game_board.theme.name)
+    }
+    fn get_key_input_from_stdin(&self)->Key{
+        read_key(self.key_reader)
+    }
+}
+/// the next key pressed,if any
+fn read_key(keys:&Receiver<u8>)->Key{
+    match keys.try_recv() {
+        Ok(byte) => match byte {
+            b'a' => Key::Left,
+            b'd' => Key::Right,
+            b'w' => Key::Up,
+            b's' => Key::Down,
+            b'p' => Key::Pause,
+            b'q' => Key::Quit,
+            b't' => Key::Theme,
+            b'\r' | b'\n' | b' ' => Key::Select,
+            b'y' => Key::Yes,
+            b'n' => Key::No,
+            _ => Key::Other
+        },
+        Err(std::sync::mpsc::TryRecvError::Empty) => Key::Nothing,
+        Err(std::sync::mpsc::TryRecvError::Disconnected) => Key::Closed
     }
 }
 struct Board{
+    /// terminal size
     raws:usize,
     columns:usize,
+    /// well size in cells,height counts the hidden raws too
+    width:usize,
+    height:usize,
+    /// buffer raws above the visible part of the well
+    hidden:usize,
+    /// locked cells of the well,row by row from the top
+    cells:Vec<Option<BlockType>>,
     blocks:Blocks,
-    blocks_position:(usize,usize),
+    /// well column of the left side and well raw of the bottom side of the blocks square,
+    /// the square may hang over the walls and the top as long as its pixels fit
+    blocks_position:(isize,isize),
+    /// top left corner of the frame on the screen
+    origin:(usize,usize),
+    /// the terminal can not hold the frame
+    too_small:bool,
+    /// the coming blocks,first the next one
+    queue:Vec<BlockType>,
+    score:u32,
+    speed:u32,
+    lines:u32,
+    /// whole seconds played
+    seconds:u32,
+    /// blocks dealt of each type
+    stats:[u32;6],
+    /// which panels the sidebars should show
+    panels:layout::Panels,
+    /// the panels that fit the frame and where they are
+    placed:Vec<layout::Placed>,
+    /// column of the left wall of the well in the frame,0 without a left sidebar
+    well_left:usize,
     matrix:Vec<Pixel>,
+    /// the screen as it was last drawn,empty when it has to be drawn from scratch
+    previous:Vec<Pixel>,
+    /// the colors the terminal was left with by the last frame
+    brush:Option<Brush>,
+    color_support:ColorSupport,
+    /// the terminal can not show Unicode,themes are drawn with ASCII only
+    ascii:bool,
+    /// show where the falling blocks would land
+    show_ghost:bool,
+    /// a cell is one column and half a raw,two raws of the well share a raw of the screen
+    half_blocks:bool,
+    /// columns and raws of the screen a cell takes when not in half block mode
+    cell_size:(usize,usize),
+    theme:Theme,
     write_cache:Vec<u8>,
+    /// when set the well is hidden and the lines of the text are shown in its middle
+    banner:Option<String>,
+    /// false between locking a blocks and spawning the next one
+    show_blocks:bool,
+    /// full raws being wiped and how many cells are wiped on each side of their middle
+    clearing:Vec<usize>,
+    wipe:usize,
+    /// lines over the top raws of the well,the state of a replay
+    overlay:Option<String>,
 }
 impl Board {
-    fn new(dimensions:(u16,u16),current_type:BlockType)->Board{
-        Board { raws: dimensions.0 as usize, columns: dimensions.1 as usize, blocks:Blocks::new(current_type),
-            blocks_position:(((dimensions.1 as usize-10)/2-4)/2,1),matrix:vec![Pixel::default();(dimensions.0*dimensions.1) as usize],
-            write_cache:vec![0;(dimensions.0*dimensions.1*(10)) as usize] }
+    /// a board laid out as the config says,current is the falling blocks and queue the coming ones
+    fn from_config(config:&config::Config,dimensions:(u16,u16),current:BlockType,queue:Vec<BlockType>)->Board{
+        let mut board = Board::new((config.width,config.height,config.hidden),(0,0),current,current);
+        board.queue = queue;
+        board.color_support = config.colors.unwrap_or_else(ColorSupport::detect);
+        board.ascii = config.ascii.unwrap_or_else(|| !theme::locale_is_utf8());
+        board.show_ghost = config.ghost;
+        board.half_blocks = config.half_blocks;
+        board.cell_size = config.cell_size;
+        board.panels = config.panels.clone();
+        board.theme = if board.ascii {config.theme.clone().ascii()} else {config.theme.clone()};
+        board.speed = config.rules.start_speed;
+        board.layout(dimensions);
+        board
     }
-    fn set_pixel(&mut self,x:usize,y:usize,c:char,fc:FrontColor,bc:BackColor){
-        self.matrix.as_mut_slice()[x+y*self.columns].change_all(c, fc, bc);
+    /// well is (width,visible raws,hidden raws)
+    fn new(well:(usize,usize,usize),dimensions:(u16,u16),current_type:BlockType,next_type:BlockType)->Board{
+        let height = well.1+well.2;
+        let mut board = Board { raws: 0, columns: 0, width: well.0, height, hidden: well.2, cells: vec![None;well.0*height],
+            blocks:Blocks::new(current_type), blocks_position:((well.0 as isize-4)/2,well.2 as isize), origin:(0,0), too_small:false,
+            queue:vec![next_type], score:0, speed:5, lines:0, seconds:0, stats:[0;6], panels:layout::Panels::default(), placed:Vec::new(), well_left:0, matrix:Vec::new(), previous:Vec::new(), brush:None, color_support:ColorSupport::TrueColor, ascii:false, show_ghost:true, half_blocks:false, cell_size:(2,1), theme:Theme::default(), write_cache:Vec::new(), banner:None,
+            show_blocks:true, clearing:Vec::new(), wipe:0, overlay:None };
+        board.layout(dimensions);
+        board
     }
+    /// raws and columns of the screen the visible well takes
+    fn well_size(&self)->(usize,usize){
+        if self.half_blocks{
+            ((self.height-self.hidden).div_ceil(2),self.width)
+        }else {
+            ((self.height-self.hidden)*self.cell_size.1,self.width*self.cell_size.0)
+        }
+    }
+    /// size of the frame around the well and the sidebars,(raws,columns)
+    fn frame_size(&self)->(usize,usize){
+        let (raws,columns) = self.well_size();
+        (raws+2,self.well_left+columns+11)
+    }
+    /// raws of one blocks preview in a panel
+    fn preview_raws(&self)->usize{
+        if self.half_blocks {2} else {4}
+    }
+    fn set_theme(&mut self,theme:Theme){
+        self.theme = if self.ascii {theme.ascii()} else {theme};
+        self.layout((self.raws as u16,self.columns as u16));
+    }
+    /// recompute the screen for a terminal of the given size,the well is kept as it is
+    fn layout(&mut self,dimensions:(u16,u16)){
+        self.raws = dimensions.0 as usize;
+        self.columns = dimensions.1 as usize;
+        // the left sidebar only when the terminal is wide enough for both
+        let (well_raws,well_columns) = self.well_size();
+        let left_columns = if self.panels.left.is_empty() {0} else {9};
+        self.well_left = if well_columns+11+left_columns <= self.columns {left_columns} else {0};
+        self.placed = self.panels.place(well_raws, self.well_left > 0, self.preview_raws());
+        let (frame_raws,frame_columns) = self.frame_size();
+        self.too_small = self.raws < frame_raws || self.columns < frame_columns;
+        self.origin = ((self.columns.saturating_sub(frame_columns))/2,(self.raws.saturating_sub(frame_raws))/2);
+        self.matrix = vec![Pixel::default();self.raws*self.columns];
+        self.previous.clear();
+        if !self.too_small{
+            self.init();
+            for placed in self.placed.clone(){
+                self.draw_panel(placed);
+            }
+        }
+    }
+    fn set_pixel(&mut self,x:usize,y:usize,c:char,fc:Color,bc:Color){
+        self.matrix.as_mut_slice()[self.origin.0+x+(self.origin.1+y)*self.columns].change_all(c, fc, bc);
+    }
+    fn put_pixel(&mut self,x:usize,y:usize,pixel:Pixel){
+        self.matrix.as_mut_slice()[self.origin.0+x+(self.origin.1+y)*self.columns] = pixel;
+    }
+    /// the frame,with a wall between each sidebar and the well
     fn init(&mut self){
-        for raw in 0..self.raws  {
-            for column in 0..self.columns  {
-                if raw == 0{
-                    if column == 0{
-                        self.set_pixel(column, raw, '┌', FrontColor::Default, BackColor::Default);
-                    }
-                    else if column == (self.columns-10)  {
-                        self.set_pixel(column, raw, '┬', FrontColor::Default, BackColor::Default);
-                    }
-                    else if column == (self.columns-1)  {
-                        self.set_pixel(column, raw, '┐', FrontColor::Default, BackColor::Default);
-                    }else {
-                        self.set_pixel(column, raw, '─', FrontColor::Default, BackColor::Default);
-                    }
-                }
-                else if raw == (self.raws-1)  {
-                    if column == 0{
-                        self.set_pixel(column, raw, '└', FrontColor::Default, BackColor::Default);
-                    }
-                    else if column == (self.columns-10)  {
-                        self.set_pixel(column, raw, '┴', FrontColor::Default, BackColor::Default);
-                    }
-                    else if column == (self.columns-1)  {
-                        self.set_pixel(column, raw, '┘', FrontColor::Default, BackColor::Default);
-                    }else {
-                        self.set_pixel(column, raw, '─', FrontColor::Default, BackColor::Default);
-                    }
-                }
-                else if column == 0 || column == (self.columns-1)   || column == (self.columns-10)  {
-                    self.set_pixel(column, raw, '│', FrontColor::Default, BackColor::Default);
-                }
+        let (raws,columns) = self.frame_size();
+        let walls = [0,self.well_left,columns-10,columns-1];
+        let border = self.theme.border;
+        for raw in 0..raws{
+            for column in 0..columns{
+                let wall = walls.contains(&column);
+                let c = match (raw,column) {
+                    (0,0) => border[0],
+                    (0,_) if column == columns-1 => border[2],
+                    (0,_) if wall => border[1],
+                    (_,0) if raw == raws-1 => border[4],
+                    (_,_) if raw == raws-1 && column == columns-1 => border[6],
+                    (_,_) if raw == raws-1 && wall => border[5],
+                    (_,_) if raw == 0 || raw == raws-1 => border[7],
+                    (_,_) if wall => border[3],
+                    _ => continue
+                };
+                self.set_pixel(column, raw, c, self.theme.border_color, Color::Default);
             }
         }
     }
-    fn draw_next_block(&mut self,next:BlockType){
-        for (index,character) in "next:".chars().enumerate(){
-            self.set_pixel(self.columns-9+index, 1, character, FrontColor::Default, BackColor::Red);
+    /// first column inside the sidebar of the panel
+    fn panel_x(&self,placed:layout::Placed)->usize{
+        if placed.left {1} else {self.frame_size().1-9}
+    }
+    /// draw the panel again if it is shown
+    fn redraw_panel(&mut self,panel:layout::Panel){
+        if self.too_small{
+            return;
         }
-        match next {
-            BlockType::Itype => {
-                for x in 0..6{
-                    for y in 0..4{
-                        if x <2{
-                            self.set_pixel(self.columns-9+x, 2+y, ' ', FrontColor::Default, next.get_color());
-                        }
-                        else {
-                            self.set_pixel(self.columns-9+x, 2+y, ' ', FrontColor::Default, BackColor::Default);
-                        }
-                    }
-                }
-            },
-            BlockType::Ltype => {
-                for x in 0..6{
-                    for y in 0..4{
-                        if (x <2 && y>=1) || (x<4 && y == 3){
-                            self.set_pixel(self.columns-9+x, 2+y, ' ', FrontColor::Default, next.get_color());
-                        }
-                        else {
-                            self.set_pixel(self.columns-9+x, 2+y, ' ', FrontColor::Default, BackColor::Default);
-                        }
-                    }
-                }
-            },
-            BlockType::Otype => {
-                for x in 0..6{
-                    for y in 0..4{
-                        if x <4 && y>1{
-                            self.set_pixel(self.columns-9+x, 2+y, ' ', FrontColor::Default, next.get_color());
-                        }
-                        else {
-                            self.set_pixel(self.columns-9+x, 2+y, ' ', FrontColor::Default, BackColor::Default);
-                        }
-                    }
-                }
-            },
-            BlockType::Stype =>{
-                for x in 0..6{
-                    for y in 0..4{
-                        if (y == 2 && x>= 2) || (x<4 && y == 3){
-                            self.set_pixel(self.columns-9+x, 2+y, ' ', FrontColor::Default, next.get_color());
-                        }else {
-                            self.set_pixel(self.columns-9+x, 2+y, ' ', FrontColor::Default, BackColor::Default);
-                        }
-                    }
+        if let Some(placed) = self.placed.iter().find(|placed| placed.panel == panel).copied(){
+            self.draw_panel(placed);
+        }
+    }
+    fn draw_panel(&mut self,placed:layout::Placed){
+        use layout::Panel;
+        let x = self.panel_x(placed);
+        for dy in 0..placed.height{
+            for dx in 0..8{
+                self.put_pixel(x+dx, placed.raw+dy, Pixel::default());
+            }
+        }
+        // the theme has colors for three labels,the other panels share them
+        let (front,back) = match placed.panel {
+            Panel::Next => self.theme.labels[0],
+            Panel::Score | Panel::Lines | Panel::Timer => self.theme.labels[1],
+            Panel::Speed | Panel::Stats | Panel::Danger => self.theme.labels[2],
+        };
+        let label = match placed.panel {
+            Panel::Timer => "time:".to_string(),
+            Panel::Danger => return,
+            panel => format!("{}:",panel.name())
+        };
+        for (index,character) in label.chars().enumerate(){
+            self.set_pixel(x+index, placed.raw, character, front, back);
+        }
+        let value = match placed.panel {
+            Panel::Score => self.score.to_string(),
+            Panel::Speed => self.speed.to_string(),
+            Panel::Lines => self.lines.to_string(),
+            Panel::Timer => format!("{}:{:02}",self.seconds/60,self.seconds%60),
+            _ => String::new()
+        };
+        let bold = if placed.panel == Panel::Score {Attributes::BOLD} else {Attributes::default()};
+        for (index,character) in value.chars().enumerate(){
+            self.put_pixel(x+index, placed.raw+1, Pixel::new(character, self.theme.value_color, Color::Default).with_attributes(bold));
+        }
+        match placed.panel {
+            Panel::Next => {
+                let preview = self.preview_raws();
+                for (index,t) in self.queue.clone().into_iter().take((placed.height-1)/preview).enumerate(){
+                    self.draw_preview(x, placed.raw+1+index*preview, t);
                 }
             },
-            BlockType::Ttype =>{
-                for x in 0..6{
-                    for y in 0..4{
-                        if (x == 2||x==3) && y==2 || y==3{
-                            self.set_pixel(self.columns-9+x, 2+y, ' ', FrontColor::Default, next.get_color());
-                        }
-                        else{
-                            self.set_pixel(self.columns-9+x, 2+y, ' ', FrontColor::Default, BackColor::Default);
-                        }
+            Panel::Stats => {
+                const TYPES:[(BlockType,char);6] = [(BlockType::Ttype,'T'),(BlockType::Ztype,'Z'),(BlockType::Stype,'S'),
+                    (BlockType::Ltype,'L'),(BlockType::Itype,'I'),(BlockType::Otype,'O')];
+                for (index,(t,name)) in TYPES.into_iter().enumerate(){
+                    self.set_pixel(x, placed.raw+1+index, name, self.theme.pieces[t as usize], Color::Default);
+                    for (offset,character) in self.stats[t as usize].to_string().chars().enumerate(){
+                        self.set_pixel(x+2+offset, placed.raw+1+index, character, self.theme.value_color, Color::Default);
                     }
                 }
             },
-            BlockType::Ztype =>{
-                for x in 0..6{
-                    for y in 0..4{
-                        if (x <4 && y==2) || (x>1 && y == 3){
-                            self.set_pixel(self.columns-9+x, 2+y, ' ', FrontColor::Default, next.get_color());
-                        }
-                        else {
-                            self.set_pixel(self.columns-9+x, 2+y, ' ', FrontColor::Default, BackColor::Default);
-                        }
+            _ => {}
+        }
+    }
+    /// the blocks as it spawns,with its square at (x,y) of the frame
+    fn draw_preview(&mut self,x:usize,y:usize,t:BlockType){
+        let square = Blocks::new(t).square;
+        let filled = |column:usize,raw:usize| !square[column+raw*4].back_color.is_default();
+        for raw in 0..self.preview_raws(){
+            for column in 0..4{
+                if self.half_blocks{
+                    let color = |raw:usize| filled(column,raw).then_some(self.theme.pieces[t as usize]);
+                    let pixel = self.half_pixel(color(2*raw), color(2*raw+1));
+                    self.put_pixel(x+column, y+raw, pixel);
+                }else if filled(column,raw){
+                    for half in 0..2{
+                        let pixel = self.piece_pixel(Some(t), half);
+                        self.put_pixel(x+2*column+half, y+raw, pixel);
                     }
                 }
             }
         }
     }
-    fn draw_score(&mut self,socre:u32){
-        for (index,character) in "score:".chars().enumerate(){
-            self.set_pixel(self.columns-9+index, 10+(self.raws-10)/5*3, character, FrontColor::Default, BackColor::Green);
+    fn draw_queue(&mut self,queue:Vec<BlockType>){
+        self.queue = queue;
+        self.redraw_panel(layout::Panel::Next);
+    }
+    fn draw_score(&mut self,score:u32){
+        self.score = score;
+        self.redraw_panel(layout::Panel::Score);
+    }
+    fn draw_speed(&mut self,speed:u32){
+        self.speed = speed;
+        self.redraw_panel(layout::Panel::Speed);
+    }
+    fn draw_lines(&mut self,lines:u32){
+        self.lines = lines;
+        self.redraw_panel(layout::Panel::Lines);
+    }
+    /// false when the shown time did not change
+    fn draw_time(&mut self,milliseconds:u32)->bool{
+        let seconds = milliseconds/1000;
+        let changed = seconds != self.seconds;
+        self.seconds = seconds;
+        if changed{
+            self.redraw_panel(layout::Panel::Timer);
+        }
+        changed
+    }
+    fn draw_stats(&mut self,stats:[u32;6]){
+        self.stats = stats;
+        self.redraw_panel(layout::Panel::Stats);
+    }
+    /// does the blocks cover the cell (x,y) when its square is put at position
+    fn covers(&self,position:(isize,isize),x:usize,y:usize)->bool{
+        let (dx,dy) = (x as isize-position.0,y as isize-position.1+3);
+        (0..4).contains(&dx) && (0..4).contains(&dy) && !self.blocks.square[(dx+dy*4) as usize].back_color.is_default()
+    }
+    /// what fills the cell (x,y) of the well,including the falling blocks
+    fn cell_type(&self,x:usize,y:usize)->Option<BlockType>{
+        if self.clearing.contains(&y) && (2*x+1).abs_diff(self.width) < 2*self.wipe{
+            return None;
         }
-        for (index,character) in socre.to_string().chars().enumerate(){
-            self.set_pixel(self.columns-9+index, 10+(self.raws-10)/5*3+1, character, FrontColor::Yellow, BackColor::Default);
+        if self.show_blocks && self.covers(self.blocks_position, x, y){
+            return Some(self.blocks.t);
         }
+        self.cells[y*self.width+x]
     }
-    fn draw_speed(&mut self,speed:u32){
-        for (index,character) in "speed:".chars().enumerate(){
-            self.set_pixel(self.columns-9+index, 10+(self.raws-10)/5*4, character, FrontColor::Default, BackColor::Blue);
+    /// where the blocks would land if it dropped straight down
+    fn ghost_position(&self)->(isize,isize){
+        let mut position = self.blocks_position;
+        while self.fits(&self.blocks, (position.0,position.1+1)){
+            position.1+=1;
         }
-        for (index,character) in speed.to_string().chars().enumerate(){
-            self.set_pixel(self.columns-9+index, 10+(self.raws-10)/5*4+1, character, FrontColor::Yellow, BackColor::Default);
+        position
+    }
+    /// the stack reaches into the top raws of the visible well
+    fn in_danger(&self)->bool{
+        self.cells[..(self.hidden+4).min(self.height)*self.width].iter().any(|cell| cell.is_some())
+    }
+    /// left (half 0) or right (half 1) pixel of a cell drawn with the theme,
+    /// wider cells repeat the left character over their left half and the right one over the rest
+    fn piece_pixel(&self,t:Option<BlockType>,half:usize)->Pixel{
+        // without colors only the characters tell the pieces apart
+        const MONO_CELLS:[[char;2];6] = [['<','>'],['%','%'],['#','#'],['[',']'],['(',')'],['@','@']];
+        match t {
+            Some(t) if self.color_support == ColorSupport::Mono => Pixel::new(MONO_CELLS[t as usize][half], Color::Default, Color::Default).with_attributes(Attributes::BOLD),
+            Some(t) if self.theme.fill_back => Pixel::new(self.theme.cell[half], Color::Default, self.theme.pieces[t as usize]),
+            Some(t) => Pixel::new(self.theme.cell[half], self.theme.pieces[t as usize], Color::Default),
+            None => Pixel::default()
         }
     }
-    fn draw(&mut self){
-        let mut position = 0;
-        for (index,pixel) in self.matrix.iter().enumerate(){
-            let (x,y) = (index%self.columns,index/self.columns);
-            if x>0 && x<self.columns-10 && y >0 && y<self.raws-1{
-                let real_x = (x-1)/2;
-                if real_x >= self.blocks_position.0 && real_x<= self.blocks_position.0+3 && y <= self.blocks_position.1 && y>= self.blocks_position.1.checked_sub(3).unwrap_or(1){
-                    if self.blocks.square[real_x-self.blocks_position.0+(3+y-self.blocks_position.1)*4].back_color.is_default(){
-                        position+=pixel.encode_ascii(&mut self.write_cache[position..]);
+    /// color of the cell (x,y) of the well in half block mode,the ghost is grey
+    fn half_color(&self,x:usize,y:usize,ghost:Option<(isize,isize)>)->Option<Color>{
+        if y >= self.height{
+            return None;
+        }
+        match self.cell_type(x, y) {
+            Some(t) => Some(self.theme.pieces[t as usize]),
+            None if ghost.is_some_and(|position| self.covers(position, x, y)) => Some(Color::Bright(0)),
+            None => None
+        }
+    }
+    /// one screen pixel showing an upper and a lower cell,
+    /// without colors or Unicode only the shape is shown
+    fn half_pixel(&self,top:Option<Color>,bottom:Option<Color>)->Pixel{
+        let (upper,lower,full) = if self.ascii {('\'','.',':')} else {('▀','▄','█')};
+        match (top,bottom) {
+            (None,None) => Pixel::default(),
+            (Some(top),None) => Pixel::new(upper, top, Color::Default),
+            (None,Some(bottom)) => Pixel::new(lower, bottom, Color::Default),
+            (Some(top),Some(_)) if self.ascii || self.color_support == ColorSupport::Mono => Pixel::new(full, top, Color::Default),
+            (Some(top),Some(bottom)) => Pixel::new(upper, top, bottom)
+        }
+    }
+    /// a dim outline of the piece pixel
+    fn ghost_pixel(&self,t:BlockType,half:usize)->Pixel{
+        let solid = self.piece_pixel(Some(t), half);
+        let pixel = if solid.back_color.is_default() {
+            Pixel { attributes: Attributes::default(), ..solid }
+        }else {
+            Pixel::new(['[',']'][half], solid.back_color, Color::Default)
+        };
+        pixel.with_attributes(Attributes::DIM)
+    }
+    /// the whole screen as it should look now
+    fn compose(&self)->Vec<Pixel>{
+        if self.too_small{
+            let mut screen = vec![Pixel::default();self.raws*self.columns];
+            for (index,c) in "terminal too small".chars().take(screen.len()).enumerate(){
+                screen[index].change_character(c);
+            }
+            return screen;
+        }
+        let raws = self.frame_size().0;
+        let well_columns = self.well_size().1;
+        let banner:Vec<Vec<char>> = self.banner.iter().flat_map(|text| text.lines().map(|line| line.chars().collect())).collect();
+        let banner_y = raws.saturating_sub(banner.len())/2;
+        let ghost = self.ghost_position();
+        let show_ghost = self.show_ghost && self.show_blocks && ghost != self.blocks_position;
+        let danger = self.placed.iter().find(|placed| placed.panel == layout::Panel::Danger && self.in_danger())
+            .map(|placed| (self.panel_x(*placed),placed.raw));
+        self.matrix.iter().enumerate().map(|(index,pixel)| {
+            let (x,y) = ((index%self.columns).wrapping_sub(self.origin.0),(index/self.columns).wrapping_sub(self.origin.1));
+            let pixel = if x>self.well_left && x<=self.well_left+well_columns && y >0 && y<raws-1{
+                // from 1 at the left wall
+                let x = x-self.well_left;
+                if let Some(line) = self.overlay.as_ref().and_then(|overlay| overlay.lines().nth(y-1)){
+                    Pixel::new(line.chars().nth(x-1).unwrap_or(' '), Color::Default, Color::Default).with_attributes(Attributes::REVERSE)
+                }else if self.banner.is_some(){
+                    let line = y.checked_sub(banner_y).and_then(|i| banner.get(i)).map_or(&[][..], |line| &line[..]);
+                    let banner_x = well_columns.saturating_sub(line.len())/2;
+                    let c = (x-1).checked_sub(banner_x).and_then(|i| line.get(i)).copied().unwrap_or(' ');
+                    // the text stands out in reverse video with a space of margin on each side
+                    if !line.is_empty() && x >= banner_x && x <= banner_x+line.len()+1{
+                        Pixel::new(c, Color::Default, Color::Default).with_attributes(Attributes::REVERSE)
                     }else {
-                        position+=self.blocks.square[real_x-self.blocks_position.0+(3+y-self.blocks_position.1)*4].encode_ascii(&mut self.write_cache[position..]);
+                        Pixel::new(c, Color::Default, Color::Default)
                     }
+                }else if self.half_blocks{
+                    let (cell_x,cell_y) = (x-1,2*(y-1)+self.hidden);
+                    let ghost = show_ghost.then_some(ghost);
+                    self.half_pixel(self.half_color(cell_x, cell_y, ghost), self.half_color(cell_x, cell_y+1, ghost))
                 }else {
-                    position+=pixel.encode_ascii(&mut self.write_cache[position..]);
+                    let (cell_width,cell_height) = self.cell_size;
+                    let (cell_x,cell_y) = ((x-1)/cell_width,(y-1)/cell_height+self.hidden);
+                    let half = (x-1)%cell_width*2/cell_width;
+                    match self.cell_type(cell_x, cell_y) {
+                        None if show_ghost && self.covers(ghost, cell_x, cell_y) => self.ghost_pixel(self.blocks.t, half),
+                        t => self.piece_pixel(t, half)
+                    }
                 }
             }
-            else {
-                position+=pixel.encode_ascii(&mut self.write_cache[position..]);
+            else if let Some(c) = danger.filter(|danger| danger.1 == y).and_then(|danger| "danger".chars().nth(x.wrapping_sub(danger.0))){
+                Pixel::new(c, self.theme.danger_color, Color::Default).with_attributes(Attributes::BOLD.with(Attributes::BLINK))
             }
-            if index%self.columns == self.columns-1 && index < self.columns*self.raws-1{
-                position+=push_char_into_array('\n', &mut self.write_cache[position..]);
+            else {
+                *pixel
+            };
+            pixel.fit(self.color_support)
+        }).collect()
+    }
+    /// write only the pixels that changed since the last frame,
+    /// a run of changed pixels on one raw needs a single cursor move
+    fn draw(&mut self){
+        // unchanged pixels shorter than this between two changed ones are rewritten rather than jumped over
+        const GAP:usize = 4;
+        let screen = self.compose();
+        self.write_cache.clear();
+        let full = self.previous.len() != screen.len();
+        if full{
+            // clear with the default colors,some terminals fill with the current background
+            self.write_cache.extend_from_slice(b"\x1b[0m\x1b[2J");
+            self.brush = Some(Brush { front_color: Color::Default, back_color: Color::Default, attributes: Attributes::default() });
+        }
+        let changed = |index:usize| full || screen[index] != self.previous[index];
+        for y in 0..self.raws{
+            let mut x = 0;
+            while x<self.columns{
+                if !changed(y*self.columns+x){
+                    x+=1;
+                    continue;
+                }
+                let mut end = x+1;
+                while let Some(next) = (end..(end+GAP).min(self.columns)).find(|&i| changed(y*self.columns+i)){
+                    end = next+1;
+                }
+                push_cursor(x, y, &mut self.write_cache);
+                for pixel in &screen[y*self.columns+x..y*self.columns+end]{
+                    pixel.encode_ascii(&mut self.write_cache, &mut self.brush);
+                }
+                x = end;
             }
         }
-        reset();
+        self.previous = screen;
         std::io::stdout().write_all(&self.write_cache).unwrap();
         std::io::stdout().flush().unwrap();
     }
-    fn is_fill_line(&self,line_num:usize)->bool{
-        for x in 1..self.columns-10{
-            if self.matrix[line_num*self.columns+x].back_color.is_default(){
-                return false;
-            }
+    /// does every pixel of the blocks land on a free cell of the well
+    /// when its square is put at position,raws above the well count as free
+    fn fits(&self,blocks:&Blocks,position:(isize,isize))->bool{
+        blocks.square.iter().enumerate().all(|(index,pixel)| {
+            let (x,y) = (position.0+(index%4) as isize,position.1-3+(index/4) as isize);
+            pixel.back_color.is_default() ||
+            (x >= 0 && x < self.width as isize && y < self.height as isize &&
+            (y < 0 || self.cells[y as usize*self.width+x as usize].is_none()))
+        })
+    }
+    /// move the blocks if it fits at the new place
+    fn try_move(&mut self,dx:isize,dy:isize)->bool{
+        let position = (self.blocks_position.0+dx,self.blocks_position.1+dy);
+        let fits = self.fits(&self.blocks, position);
+        if fits{
+            self.blocks_position = position;
+        }
+        fits
+    }
+    /// turn the blocks if it fits after turning
+    fn try_rotate(&mut self)->bool{
+        let mut turned = self.blocks.clone();
+        turned.trans();
+        let fits = self.fits(&turned, self.blocks_position);
+        if fits{
+            self.blocks = turned;
         }
-        true
+        fits
+    }
+    fn is_fill_line(&self,line_num:usize)->bool{
+        self.cells[line_num*self.width..(line_num+1)*self.width].iter().all(|cell| cell.is_some())
+    }
+    fn full_lines(&self)->Vec<usize>{
+        (0..self.height).filter(|&y| self.is_fill_line(y)).collect()
     }
     fn remove_line(&mut self)->u32{
         let mut jump_num:usize = 0;
-        let mut y = self.raws-1;
+        let mut y = self.height;
         while y>0{
-            if y>jump_num{
-                if self.is_fill_line(y-jump_num){
-                    jump_num+=1;
-                    continue;
-                }else if jump_num>0{
-                    for x in 1..(self.columns-10){
-                        self.matrix[y*self.columns+x] = self.matrix[(y-jump_num)*self.columns+x];
-                    }
-                }
+            y-=1;
+            if y>=jump_num && self.is_fill_line(y-jump_num){
+                jump_num+=1;
+                y+=1;
+                continue;
             }
-            else {
-                for x in 1..(self.columns-10){
-                    self.matrix[y*self.columns+x].change_back_color(BackColor::Default);
-                }
+            for x in 0..self.width{
+                self.cells[y*self.width+x] = if y>=jump_num {self.cells[(y-jump_num)*self.width+x]} else {None};
             }
-            y-=1;
         }
         jump_num as u32
     }
-    fn is_bottom(&mut self)->bool{
-        for x in 0..4{
-            if (!self.blocks.square[12+x].back_color.is_default())&&
-            (self.matrix[(self.blocks_position.1+1)*self.columns+(self.blocks_position.0+x)*2+1].character !=' ' ||
-            !self.matrix[(self.blocks_position.1+1)*self.columns+(self.blocks_position.0+x)*2+1].back_color.is_default()||
-            self.matrix[(self.blocks_position.1+1)*self.columns+(self.blocks_position.0+x)*2+2].character !=' ' ||
-            !self.matrix[(self.blocks_position.1+1)*self.columns+(self.blocks_position.0+x)*2+2].back_color.is_default()
-            ){
-                return true;
-            }
-            if self.blocks_position.1>1 && (!self.blocks.square[8+x].back_color.is_default())&&
-            (self.matrix[(self.blocks_position.1)*self.columns+(self.blocks_position.0+x)*2+1].character !=' ' ||
-            !self.matrix[(self.blocks_position.1)*self.columns+(self.blocks_position.0+x)*2+1].back_color.is_default()||
-            self.matrix[(self.blocks_position.1)*self.columns+(self.blocks_position.0+x)*2+2].character !=' ' ||
-            !self.matrix[(self.blocks_position.1)*self.columns+(self.blocks_position.0+x)*2+2].back_color.is_default()
-            ){
-                return true;
-            }
-            if self.blocks_position.1>2 && (!self.blocks.square[4+x].back_color.is_default())&&
-            (self.matrix[(self.blocks_position.1-1)*self.columns+(self.blocks_position.0+x)*2+1].character !=' ' ||
-            !self.matrix[(self.blocks_position.1-1)*self.columns+(self.blocks_position.0+x)*2+1].back_color.is_default()||
-            self.matrix[(self.blocks_position.1-1)*self.columns+(self.blocks_position.0+x)*2+2].character !=' ' ||
-            !self.matrix[(self.blocks_position.1-1)*self.columns+(self.blocks_position.0+x)*2+2].back_color.is_default()
-            ){
-                return true;
+    /// put new blocks of type t at the top of the well,false if it overlaps the stack (block out)
+    fn enter(&mut self,t:BlockType)->bool{
+        self.blocks = Blocks::new(t);
+        self.blocks_position = ((self.width as isize-4)/2,self.hidden as isize);
+        self.show_blocks = true;
+        self.fits(&self.blocks, self.blocks_position)
+    }
+    /// the rule broken by blocks locked between raws top and bottom,if any,
+    /// raws above 0 can not be kept so locking there always ends the game
+    fn lock_out(&self,top:isize,bottom:isize,partial_lock_out:bool)->Option<TopOut>{
+        let hidden = self.hidden as isize;
+        if bottom < hidden{
+            Some(TopOut::LockOut)
+        }else if (partial_lock_out && top < hidden) || top < 0{
+            Some(TopOut::PartialLockOut)
+        }else {
+            None
+        }
+    }
+    /// write the falling blocks into the well,return the highest and lowest raw it covers
+    fn lock(&mut self,t:BlockType)->(isize,isize){
+        let (mut top,mut bottom) = (isize::MAX,isize::MIN);
+        for (index,pixel) in self.blocks.square.iter().enumerate(){
+            let (x,y) = (self.blocks_position.0+(index%4) as isize,self.blocks_position.1-3+(index/4) as isize);
+            if !pixel.back_color.is_default(){
+                top = top.min(y);
+                bottom = bottom.max(y);
+                if y >= 0{
+                    self.cells[y as usize*self.width+x as usize] = Some(t);
+                }
             }
         }
-        false
+        (top,bottom)
+    }
+    /// the blocks can not fall any further
+    fn is_bottom(&self)->bool{
+        !self.fits(&self.blocks, (self.blocks_position.0,self.blocks_position.1+1))
     }
     /// direction
     /// 0  left
     /// 1  right
     /// 2  up
     /// 3  down
-    fn mov(&mut self,direction:u8){
-        
+    /// false when the blocks could not move
+    fn mov(&mut self,direction:u8)->bool{
         match direction {
-           0 => {
-             if self.blocks_position.0>0{
-                let mut flag = true;
-                for y in self.blocks_position.1.saturating_sub(4)..self.blocks_position.1{
-                    if !self.matrix[(1+y)*self.columns+(self.blocks_position.0+self.blocks.inner_left_margin)*2].back_color.is_default() && !self.blocks.square[16-(self.blocks_position.1-y)*4+self.blocks.inner_left_margin].back_color.is_default(){
-                        flag=false;
-                        break;
-                    }
-                }
-                if flag{
-                    self.blocks_position.0-=1;
-                }
-             }
-             else {
-                 self.blocks.left_and_right_move(true);
-             }
-           },
-           1 => {
-            if self.blocks_position.0<(self.columns-10)/2-4{
-                let mut flag = true;
-                for y in self.blocks_position.1.saturating_sub(4)..self.blocks_position.1{
-                    if !self.matrix[(1+y)*self.columns+(self.blocks_position.0-self.blocks.inner_right_margin)*2+9].back_color.is_default() && !self.blocks.square[16-(self.blocks_position.1-y)*4+3-self.blocks.inner_right_margin].back_color.is_default(){
-                        flag=false;
-                        break;
-                    }
-                }
-                if flag{
-                    self.blocks_position.0+=1;
-                }
-            }else {
-                self.blocks.left_and_right_move(false);
-            }
-           },
-           2 => {
-            self.blocks.trans();
-           },
-           _ =>{
-            self.blocks_position.1+=1;
-           }
+            0 => self.try_move(-1, 0),
+            1 => self.try_move(1, 0),
+            2 => self.try_rotate(),
+            _ => self.try_move(0, 1)
         }
     }
-}
-/// user should confirm the input dimensions bigger than 31x17
-fn format_dimensions(dim:(u16,u16))->(u16,u16){
-    if (dim.0 - 2)*2 <= dim.1-11{
-        (dim.0,(dim.0-2)*2+3+8)
-    }else if dim.1%2==1{
-        ((dim.1-11)/2+2,dim.1)
-    }else {
-        ((dim.1-12)/2+2,dim.1-1)
+    /// 3 of the 4 corners around the centre of the falling T blocks are walls,floor or stack,
+    /// a t-spin when it got there by turning
+    fn t_corners(&self)->bool{
+        let cells:Vec<(isize,isize)> = self.blocks.square.iter().enumerate().filter(|(_,pixel)| !pixel.back_color.is_default())
+            .map(|(index,_)| (self.blocks_position.0+(index%4) as isize,self.blocks_position.1-3+(index/4) as isize)).collect();
+        // the centre touches the other three cells
+        let centre = cells.iter().find(|(x,y)| cells.iter().filter(|(ox,oy)| (ox-x).abs()+(oy-y).abs() == 1).count() == 3);
+        let Some(&(x,y)) = centre else {
+            return false;
+        };
+        let filled = |(x,y):(isize,isize)| x < 0 || x >= self.width as isize || y >= self.height as isize ||
+            (y >= 0 && self.cells[y as usize*self.width+x as usize].is_some());
+        [(x-1,y-1),(x+1,y-1),(x-1,y+1),(x+1,y+1)].into_iter().filter(|corner| filled(*corner)).count() >= 3
     }
 }
+/// a seed that differs from game to game
+fn new_seed()->u64{
+    let time = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+    time.as_nanos() as u64 ^ (std::process::id() as u64) << 32
+}
 fn main() {
-    set_mode(false);
-    reset();
-    hide_cursor(true);
+    let mut args:Vec<String> = std::env::args().skip(1).collect();
+    // block_rain replay FILE plays a recorded game instead
+    let replay = if args.first().is_some_and(|command| command == "replay"){
+        if args.len() < 2{
+            eprintln!("missing replay file\n{}",config::USAGE);
+            std::process::exit(2);
+        }
+        let path = args.drain(..2).nth(1).unwrap();
+        match replay::Replay::load(&path) {
+            Ok(replay) => Some(replay),
+            Err(message) => {
+                eprintln!("{}",message);
+                std::process::exit(2);
+            }
+        }
+    }else {
+        None
+    };
+    let mut config = match config::Config::load(&args) {
+        Ok(config) => config,
+        Err(config::ArgsError::Help) => {
+            println!("{}",config::USAGE);
+            return;
+        },
+        Err(config::ArgsError::Invalid(message)) => {
+            eprintln!("{}",message);
+            std::process::exit(2);
+        }
+    };
+    if let Some(replay) = &replay{
+        replay.apply(&mut config);
+    }
     let (s,recv) = std::sync::mpsc::channel::<u8>();
+    if let Err(e) = terminal::check().and_then(|_| menu::check_size(&config)){
+        eprintln!("{}",e);
+        std::process::exit(e.exit_code());
+    }
+    let guard = terminal::TerminalGuard::new();
     std::thread::spawn(move || {
         use std::io::Read;
         let mut stdin = std::io::stdin();
         let mut buffer = [0u8; 1];
+        // dropping the sender on end of file or an error tells the game stdin is closed
         loop {
-            stdin.read_exact(&mut buffer).unwrap();
-            s.send(*buffer.first().unwrap()).unwrap();
+            match stdin.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(_) => if s.send(buffer[0]).is_err(){
+                    break;
+                },
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(_) => break
+            }
         }
     });
-    let mut game = Game::new(recv);
-    game.run();
-}
\ No newline at end of file
+    if let Some(replay) = &replay{
+        let played = replay::Player::new(replay, &config, &recv).map(|mut player| player.run());
+        drop(guard);
+        if let Some(signum) = terminal::signalled(){
+            std::process::exit(128+signum);
+        }
+        if let Err(e) = played{
+            eprintln!("{}",e);
+            std::process::exit(e.exit_code());
+        }
+        return;
+    }
+    let mut menu = menu::Menu::new(&recv);
+    let mut summary = None;
+    let mut seed = new_seed();
+    let mut scores = scores::Scores::load();
+    let mut play = matches!(menu.run(&mut config,&scores),menu::Choice::Play);
+    while play{
+        let mut game = match Game::new(&recv,&config,seed) {
+            Ok(game) => game,
+            Err(e) => {
+                menu.message = Some(e.to_string());
+                play = matches!(menu.run(&mut config,&scores),menu::Choice::Play);
+                continue;
+            }
+        };
+        let outcome = game.run();
+        let won = matches!(outcome,Outcome::Win);
+        let mut results = game.results(outcome);
+        summary = Some(results.summary());
+        if terminal::signalled().is_some(){
+            break;
+        }
+        menu.message = None;
+        if let Err(e) = game.replay.save("last"){
+            menu.message = Some(e);
+        }
+        if game.pieces > 0{
+            let saved = profiles::Profile::load(&config.profile).and_then(|mut profile| {
+                profile.add(config.rules.mode, won, game.lines, game.tetrises, game.t_spins, game.time);
+                profile.save()
+            });
+            if let Err(e) = saved{
+                menu.message = Some(e);
+            }
+        }
+        let score = scores::Score { mode: config.rules.mode, score: game.score, lines: game.lines, time: game.time,
+            date: scores::today(), seed: game.seed, name: String::new() };
+        // a game left before anything happened is not a record
+        if let Some(rank) = scores.rank(&score).filter(|_| score.score > 0 || score.lines > 0){
+            let name = match menu.prompt(&mut game.game_board, &format!("new record #{}\n\nname",rank), &config.profile) {
+                Some(name) if name.is_empty() => config.profile.clone(),
+                Some(name) => name,
+                None => break
+            };
+            scores.insert(scores::Score { name, ..score });
+            results.record = Some(rank);
+            if let Err(e) = scores.save(){
+                menu.message = Some(e);
+            }
+        }
+        play = match menu.results(&mut game.game_board, &results, &mut config, &scores, &game.replay) {
+            menu::AfterGame::Retry => true,
+            menu::AfterGame::NewSeed => {
+                seed = new_seed();
+                true
+            },
+            menu::AfterGame::Menu => {
+                seed = new_seed();
+                matches!(menu.run(&mut config,&scores),menu::Choice::Play)
+            },
+            menu::AfterGame::Quit => false
+        };
+    }
+    drop(guard);
+    if let Some(signum) = terminal::signalled(){
+        std::process::exit(128+signum);
+    }
+    // the game screen is gone with the alternate screen,leave the last result in the scrollback
+    if let Some(summary) = summary{
+        println!("{}",summary);
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board(t:BlockType)->Board{
+        Board::new((10,20,4),(40,80),t,BlockType::Otype)
+    }
+    /// a board laid out from the config as changed,with the colors and characters fixed
+    fn config_board(dimensions:(u16,u16),change:impl FnOnce(&mut config::Config))->Board{
+        let mut config = config::Config { colors: Some(ColorSupport::TrueColor), ascii: Some(false), ..config::Config::default() };
+        change(&mut config);
+        Board::from_config(&config, dimensions, BlockType::Otype, vec![BlockType::Otype])
+    }
+    /// well coordinates of every pixel of the falling blocks
+    fn blocks_cells(board:&Board)->Vec<(isize,isize)>{
+        board.blocks.square.iter().enumerate().filter(|(_,pixel)| !pixel.back_color.is_default())
+            .map(|(index,_)| (board.blocks_position.0+(index%4) as isize,board.blocks_position.1-3+(index/4) as isize)).collect()
+    }
+    #[test]
+    fn sgr_only_for_changed_colors(){
+        let mut buffer = Vec::new();
+        let mut brush = None;
+        Pixel::new('a', Color::Red, Color::Blue).encode_ascii(&mut buffer, &mut brush);
+        Pixel::new('b', Color::Red, Color::Blue).encode_ascii(&mut buffer, &mut brush);
+        Pixel::new('c', Color::Red, Color::Default).encode_ascii(&mut buffer, &mut brush);
+        Pixel::new('─', Color::Default, Color::Default).encode_ascii(&mut buffer, &mut brush);
+        Pixel::new('d', Color::Default, Color::Default).with_attributes(Attributes::BOLD.with(Attributes::REVERSE)).encode_ascii(&mut buffer, &mut brush);
+        Pixel::new('e', Color::Default, Color::Default).with_attributes(Attributes::REVERSE).encode_ascii(&mut buffer, &mut brush);
+        Pixel::new('f', Color::Default, Color::Default).with_attributes(Attributes::BOLD.with(Attributes::DIM)).encode_ascii(&mut buffer, &mut brush);
+        Pixel::new('g', Color::Default, Color::Default).with_attributes(Attributes::DIM.with(Attributes::UNDERLINE).with(Attributes::BLINK)).encode_ascii(&mut buffer, &mut brush);
+        assert_eq!(String::from_utf8(buffer).unwrap(),"\x1b[0;31;44mab\x1b[49mc\x1b[39m─\x1b[1;7md\x1b[22me\x1b[1;2;27mf\x1b[22;2;4;5mg");
+    }
+    #[test]
+    fn mono_uses_only_bold_and_reverse(){
+        let mut b = board(BlockType::Ttype);
+        b.color_support = ColorSupport::Mono;
+        let ghost = b.ghost_pixel(BlockType::Ttype, 0).fit(ColorSupport::Mono);
+        assert_eq!((ghost.character,ghost.attributes),('<',Attributes::default()));
+        assert_eq!(b.piece_pixel(Some(BlockType::Ttype), 0).fit(ColorSupport::Mono).attributes,Attributes::BOLD);
+        let warning = Pixel::new('d', Color::Red, Color::Default).with_attributes(Attributes::BOLD.with(Attributes::BLINK));
+        assert_eq!(warning.fit(ColorSupport::Mono).attributes,Attributes::BOLD.with(Attributes::REVERSE));
+        assert_eq!(warning.fit(ColorSupport::Ansi16).attributes,Attributes::BOLD.with(Attributes::BLINK));
+    }
+    #[test]
+    fn walls_stop_moves(){
+        let mut b = board(BlockType::Ttype);
+        while b.try_move(-1, 0){}
+        assert_eq!(blocks_cells(&b).iter().map(|c| c.0).min(),Some(0));
+        while b.try_move(1, 0){}
+        assert_eq!(blocks_cells(&b).iter().map(|c| c.0).max(),Some(9));
+    }
+    #[test]
+    fn floor_stops_drop(){
+        let mut b = board(BlockType::Otype);
+        while b.try_move(0, 1){}
+        assert!(b.is_bottom());
+        assert_eq!(blocks_cells(&b).iter().map(|c| c.1).max(),Some(23));
+    }
+    #[test]
+    fn half_blocks_pack_two_raws(){
+        let b = config_board((40,80), |config| config.half_blocks = true);
+        assert_eq!(b.frame_size(),(12,30));
+        assert!(!b.too_small);
+        let screen = b.compose();
+        // the O blocks spawns with its lower half in the first visible raw,the upper half of the first screen raw
+        let pixel = screen[(b.origin.1+1)*b.columns+b.origin.0+b.well_left+5];
+        assert_eq!((pixel.character,pixel.front_color,pixel.back_color),('▀',BlockType::Otype.get_color(),Color::Default));
+        assert_eq!(b.half_pixel(None, Some(Color::Red)).character,'▄');
+    }
+    #[test]
+    fn large_cells_scale_the_well(){
+        let b = config_board((50,80), |config| config.cell_size = (4,2));
+        assert_eq!(b.frame_size(),(42,60));
+        let screen = b.compose();
+        // the O blocks covers the cells 4 and 5 of the first visible raw,columns 17 to 24 of the frame
+        let raw = |y:usize,x:usize| screen[(b.origin.1+y)*b.columns+b.origin.0+b.well_left+x].back_color;
+        assert_eq!([raw(1,16),raw(1,17),raw(2,24),raw(2,25),raw(3,17)],
+            [Color::Default,BlockType::Otype.get_color(),BlockType::Otype.get_color(),Color::Default,Color::Default]);
+    }
+    #[test]
+    fn same_seed_deals_the_same_blocks(){
+        let deal = |seed:u64,randomizer:rules::Randomizer| {
+            let mut dealer = Dealer::new(PcgWithXorshift::new(Some(seed)), randomizer);
+            (0..12).map(|_| dealer.deal() as usize).collect::<Vec<usize>>()
+        };
+        assert_eq!(deal(7,rules::Randomizer::Random),deal(7,rules::Randomizer::Random));
+        let bags = deal(7,rules::Randomizer::Bag);
+        for bag in bags.chunks(6){
+            let mut sorted = bag.to_vec();
+            sorted.sort();
+            assert_eq!(sorted,[0,1,2,3,4,5]);
+        }
+    }
+    #[test]
+    fn ghost_lands_where_the_drop_stops(){
+        let mut b = board(BlockType::Otype);
+        for x in 0..10{
+            b.cells[20*10+x] = Some(BlockType::Itype);
+        }
+        let ghost = b.ghost_position();
+        while b.try_move(0, 1){}
+        assert_eq!(ghost,b.blocks_position);
+        assert!(!b.in_danger());
+        b.cells[5*10] = Some(BlockType::Itype);
+        assert!(b.in_danger());
+    }
+    #[test]
+    fn t_corners_count_floor_and_stack(){
+        let mut b = board(BlockType::Ttype);
+        while b.try_move(0, 1){}
+        // pointing up on the floor,the two corners below are floor
+        assert!(!b.t_corners());
+        let left = blocks_cells(&b).iter().map(|c| c.0).min().unwrap() as usize;
+        b.cells[22*10+left] = Some(BlockType::Itype);
+        assert!(b.t_corners());
+    }
+    #[test]
+    fn stack_stops_drop_and_moves(){
+        let mut b = board(BlockType::Otype);
+        for x in 0..10{
+            b.cells[20*10+x] = Some(BlockType::Itype);
+        }
+        while b.try_move(0, 1){}
+        assert_eq!(blocks_cells(&b).iter().map(|c| c.1).max(),Some(19));
+        let right = blocks_cells(&b).iter().map(|c| c.0).max().unwrap() as usize;
+        b.cells[19*10+right+1] = Some(BlockType::Itype);
+        assert!(!b.try_move(1, 0));
+    }
+    #[test]
+    fn rotation_checks_walls_and_stack(){
+        let mut b = board(BlockType::Itype);
+        while b.try_move(1, 0){}
+        assert!(!b.try_rotate());
+        let mut b = board(BlockType::Itype);
+        b.try_move(0, 10);
+        let column = blocks_cells(&b)[0].0 as usize;
+        b.cells[(b.blocks_position.1-1) as usize*10+column+1] = Some(BlockType::Otype);
+        assert!(!b.try_rotate());
+        b.cells[(b.blocks_position.1-1) as usize*10+column+1] = None;
+        assert!(b.try_rotate());
+    }
+    #[test]
+    fn full_raws_are_wiped_then_removed(){
+        let mut b = board(BlockType::Otype);
+        for x in 0..10{
+            b.cells[23*10+x] = Some(BlockType::Itype);
+        }
+        b.cells[22*10] = Some(BlockType::Ttype);
+        b.clearing = b.full_lines();
+        assert_eq!(b.clearing,vec![23]);
+        b.wipe = 1;
+        assert!(b.cell_type(4, 23).is_none());
+        assert!(b.cell_type(3, 23).is_some());
+        assert_eq!(b.remove_line(),1);
+        assert!(b.cells[23*10].is_some());
+        assert!(b.cells[23*10+1..24*10].iter().all(|cell| cell.is_none()));
+    }
+    #[test]
+    fn spawn_needs_free_cells(){
+        let mut b = board(BlockType::Otype);
+        assert!(b.fits(&b.blocks, b.blocks_position));
+        for cell in blocks_cells(&b){
+            b.cells[cell.1 as usize*10+cell.0 as usize] = Some(BlockType::Ztype);
+        }
+        assert!(!b.fits(&b.blocks, b.blocks_position));
+    }
+    #[test]
+    fn moves_are_kept_until_the_blocks_falls(){
+        let (_,keys) = std::sync::mpsc::channel();
+        let mut config = config::Config::default();
+        config.rules.start_speed = 30;
+        let mut game = Game::with_dimensions(&keys, &config, 1, (40,80));
+        let x = game.game_board.blocks_position.0;
+        // the fourth tick is a fall,its move still counts
+        for _ in 0..4{
+            game.step(Key::Right);
+        }
+        assert_eq!(game.game_board.blocks_position.0,x+4);
+        assert_eq!(game.game_board.blocks_position.1,game.game_board.hidden as isize+1);
+        // a move while the next blocks is coming goes to that blocks
+        game.phase = Phase::Entry(2*TICK);
+        game.step(Key::Right);
+        game.step(Key::Nothing);
+        assert_eq!(game.game_board.blocks_position.0,x);
+        game.step(Key::Nothing);
+        assert_eq!(game.game_board.blocks_position.0,x+1);
+    }
+    #[test]
+    fn block_out_when_the_spawn_is_taken(){
+        for hidden in [4,0]{
+            let mut b = Board::new((10,20,hidden),(40,80),BlockType::Ttype,BlockType::Otype);
+            assert!(b.enter(BlockType::Otype));
+            // the O blocks spawns on the first visible raw and the one above it
+            assert_eq!(blocks_cells(&b).iter().map(|c| c.1).max(),Some(hidden as isize));
+            let x = blocks_cells(&b)[0].0 as usize;
+            b.cells[hidden*10+x] = Some(BlockType::Ztype);
+            assert!(!b.enter(BlockType::Otype));
+        }
+    }
+    #[test]
+    fn lock_out_rules_at_the_hidden_boundary(){
+        let mut b = Board::new((10,20,4),(40,80),BlockType::Otype,BlockType::Otype);
+        // the O blocks covers raws position-1 and position
+        b.blocks_position = (3,3);
+        let (top,bottom) = b.lock(BlockType::Otype);
+        assert_eq!((top,bottom),(2,3));
+        assert_eq!(b.lock_out(top, bottom, false),Some(TopOut::LockOut));
+        b.blocks_position = (3,4);
+        let (top,bottom) = b.lock(BlockType::Otype);
+        assert_eq!(b.lock_out(top, bottom, false),None);
+        assert_eq!(b.lock_out(top, bottom, true),Some(TopOut::PartialLockOut));
+        b.blocks_position = (3,5);
+        let (top,bottom) = b.lock(BlockType::Otype);
+        assert_eq!(b.lock_out(top, bottom, true),None);
+        // without hidden raws the part above the well can not be kept
+        let mut b = Board::new((10,20,0),(40,80),BlockType::Otype,BlockType::Otype);
+        b.blocks_position = (3,0);
+        let (top,bottom) = b.lock(BlockType::Otype);
+        assert_eq!((top,bottom),(-1,0));
+        assert_eq!(b.lock_out(top, bottom, false),Some(TopOut::PartialLockOut));
+        b.blocks_position = (3,-1);
+        let (top,bottom) = b.lock(BlockType::Otype);
+        assert_eq!(b.lock_out(top, bottom, false),Some(TopOut::LockOut));
+    }
+}
@@ -0,0 +1,537 @@
+use std::sync::mpsc::{Receiver,TryRecvError};
+use raw_terminal::get_terminal_dimensions;
+use crate::config::Config;
+use crate::rules::{Mode,Randomizer};
+use crate::profiles::{self,Profile};
+use crate::replay::{Player,Replay};
+use crate::scores::{NAME_LENGTH,Scores};
+use crate::terminal::{self,StartError};
+use crate::theme::Theme;
+use crate::{BlockType,Board,Key,TICK,read_key};
+
+/// What the player picked on the title screen
+pub enum Choice {
+    Play,
+    Quit,
+}
+/// What the player picked on the results screen
+pub enum AfterGame {
+    /// play again with the same seed
+    Retry,
+    /// play again with a new seed
+    NewSeed,
+    Menu,
+    /// the terminal or stdin went away
+    Quit,
+}
+
+/// How a game went
+pub struct Results {
+    pub title:&'static str,
+    /// why the game was lost,empty otherwise
+    pub reason:&'static str,
+    pub score:u32,
+    pub lines:u32,
+    pub speed:u32,
+    /// milliseconds played
+    pub time:u32,
+    /// blocks locked
+    pub pieces:u32,
+    pub tetrises:u32,
+    pub t_spins:u32,
+    /// the place in the high scores,None when it did not get in
+    pub record:Option<usize>,
+}
+impl Results {
+    /// blocks locked per second
+    fn pps(&self)->f32{
+        if self.time == 0 {0.0} else {self.pieces as f32*1000.0/self.time as f32}
+    }
+    fn time(&self)->String{
+        format!("{}:{:02}",self.time/60000,self.time/1000%60)
+    }
+    /// one line for the scrollback
+    pub fn summary(&self)->String{
+        let reason = if self.reason.is_empty() {String::new()} else {format!(": {}",self.reason)};
+        format!("{}{}: score {},lines {},speed {},time {},{:.2} pieces per second",
+            self.title,reason,self.score,self.lines,self.speed,self.time(),self.pps())
+    }
+    /// the lines of the results screen,the reason cut to its first words,
+    /// two numbers a line so a short well still has room for the items
+    fn lines(&self)->Vec<String>{
+        let mut lines = vec![self.title.to_string(),self.reason.split(',').next().unwrap_or_default().to_string(),String::new()];
+        if let Some(rank) = self.record{
+            lines.push(format!("record #{}",rank));
+        }
+        lines.extend([format!("score {}",self.score),format!("lines {} speed {}",self.lines,self.speed),
+            format!("time {} pps {:.2}",self.time(),self.pps())]);
+        let tricks:Vec<String> = [("tetrises",self.tetrises),("t-spins",self.t_spins)].iter().filter(|(_,count)| *count > 0)
+            .map(|(name,count)| format!("{} {}",name,count)).collect();
+        if !tricks.is_empty(){
+            lines.push(tricks.join(" "));
+        }
+        lines
+    }
+}
+
+#[derive(Clone,Copy,PartialEq)]
+enum Item {
+    Play,
+    Mode,
+    Speed,
+    Randomizer,
+    Settings,
+    Quit,
+    Theme,
+    Ghost,
+    HalfBlocks,
+    Back,
+    Retry,
+    NewSeed,
+    Menu,
+    HighScores,
+    Profile,
+    NewProfile,
+    SaveReplay,
+    Replays,
+    /// the saved replay to watch
+    ReplayFile,
+    Watch,
+}
+const MAIN:[Item;9] = [Item::Play,Item::Mode,Item::Speed,Item::Randomizer,Item::Profile,Item::HighScores,Item::Replays,Item::Settings,Item::Quit];
+const SETTINGS:[Item;4] = [Item::Theme,Item::Ghost,Item::HalfBlocks,Item::Back];
+const RESULTS:[Item;5] = [Item::Retry,Item::NewSeed,Item::SaveReplay,Item::HighScores,Item::Menu];
+/// the high scores from the title screen,where the mode can be changed
+const HIGH_SCORES:[Item;2] = [Item::Mode,Item::Back];
+/// the high scores of the mode just played
+const GAME_SCORES:[Item;1] = [Item::Back];
+const PROFILE:[Item;2] = [Item::NewProfile,Item::Back];
+const REPLAYS:[Item;3] = [Item::ReplayFile,Item::Watch,Item::Back];
+
+/// the item after current in all,or before it when step is negative
+fn cycle<T:Clone+PartialEq>(all:&[T],current:&T,step:isize)->T{
+    let index = all.iter().position(|item| item == current).unwrap_or(0) as isize;
+    all[(index+step).rem_euclid(all.len() as isize) as usize].clone()
+}
+fn on_off(value:bool)->&'static str{
+    if value {"on"} else {"off"}
+}
+
+/// the terminal can hold the title screen
+pub fn check_size(config:&Config)->Result<(),StartError>{
+    let dimensions = get_terminal_dimensions().map_err(|_| StartError::NoTty)?;
+    let board = Board::from_config(config, dimensions, BlockType::Otype, Vec::new());
+    if board.too_small{
+        return Err(StartError::TooSmall { needed: board.frame_size(), got: dimensions });
+    }
+    Ok(())
+}
+
+/// The title and results screens,menus shown over the well.
+/// w and s move,a and d change a value,enter picks,q picks the last item.
+pub struct Menu<'a>{
+    keys:&'a Receiver<u8>,
+    items:&'static [Item],
+    selected:usize,
+    /// the saved replays and the one picked
+    replays:Vec<String>,
+    replay:usize,
+    /// shown under the title,the result of the last game or why it could not start
+    pub message:Option<String>,
+}
+impl<'a> Menu<'a> {
+    pub fn new(keys:&'a Receiver<u8>)->Menu<'a>{
+        Menu { keys, items: &MAIN, selected: 0, replays: Vec::new(), replay: 0, message: None }
+    }
+    fn label(&self,item:Item,config:&Config)->String{
+        match item {
+            Item::Play => "play".to_string(),
+            Item::Mode => format!("mode: {}",config.rules.mode.name()),
+            Item::Speed => format!("speed: {}",config.rules.start_speed),
+            Item::Randomizer => format!("randomizer: {}",config.rules.randomizer.name()),
+            Item::Settings => "settings".to_string(),
+            Item::Quit => "quit".to_string(),
+            Item::Theme => format!("theme: {}",config.theme.name),
+            Item::Ghost => format!("ghost: {}",on_off(config.ghost)),
+            Item::HalfBlocks => format!("half blocks: {}",on_off(config.half_blocks)),
+            Item::Back => "back".to_string(),
+            Item::Retry => "retry".to_string(),
+            Item::NewSeed => "new seed".to_string(),
+            Item::Menu => "menu".to_string(),
+            Item::HighScores => "high scores".to_string(),
+            // cut so the item fits in the well
+            Item::Profile => format!("profile: {}",config.profile.chars().take(9).collect::<String>()),
+            Item::NewProfile => "new profile".to_string(),
+            Item::SaveReplay => "save replay".to_string(),
+            Item::Replays => "replays".to_string(),
+            Item::ReplayFile => format!("replay {}/{}",self.replay+1,self.replays.len()),
+            Item::Watch => "watch".to_string(),
+        }
+    }
+    /// the label in a well too narrow for the long ones,the value without its name when it says enough
+    fn short_label(&self,item:Item,config:&Config)->String{
+        match item {
+            Item::Mode => config.rules.mode.name().to_string(),
+            Item::Speed => format!("speed {}",config.rules.start_speed),
+            Item::Randomizer => config.rules.randomizer.name().to_string(),
+            Item::Theme => config.theme.name.clone(),
+            Item::Ghost => format!("ghost {}",on_off(config.ghost)),
+            Item::HalfBlocks => format!("half {}",on_off(config.half_blocks)),
+            Item::HighScores => "scores".to_string(),
+            Item::Profile => config.profile.clone(),
+            Item::NewProfile => "new name".to_string(),
+            Item::SaveReplay => "save".to_string(),
+            Item::ReplayFile => format!("{}/{}",self.replay+1,self.replays.len()),
+            item => self.label(item, config)
+        }
+    }
+    /// the header,the message and the items in a well of (raws,columns),
+    /// the items are always there,blank lines,the hint and then the end of the header go first when short
+    fn banner(&self,header:&[String],config:&Config,(raws,columns):(usize,usize))->String{
+        let mut labels:Vec<String> = self.items.iter().map(|item| self.label(*item, config)).collect();
+        let mut marker = ["> ","  "];
+        if labels.iter().any(|label| label.chars().count()+2 > columns){
+            labels = self.items.iter().map(|item| self.short_label(*item, config)).collect();
+            marker = [">"," "];
+        }
+        let width = labels.iter().map(|label| label.chars().count()).max().unwrap_or(0);
+        // the items around the selection when they do not all fit
+        let shown = labels.len().min(raws);
+        let start = (self.selected+1).saturating_sub(shown);
+        // padded to the same width the items line up when centred
+        let items:Vec<String> = labels.iter().enumerate().skip(start).take(shown).map(|(index,label)| {
+            format!("{}{:width$}",marker[(index != self.selected) as usize],label,width = width)
+        }).collect();
+        let message:Vec<String> = self.message.iter().flat_map(|message| message.lines()).map(str::to_string).collect();
+        let hint = if columns < 12 {"enter"} else {"enter select"};
+        let mut lines = header.to_vec();
+        lines.push(String::new());
+        if !message.is_empty(){
+            lines.extend(message.iter().cloned());
+            lines.push(String::new());
+        }
+        lines.extend(items.iter().cloned());
+        lines.extend([String::new(),hint.to_string()]);
+        if lines.len() > raws{
+            let mut header:Vec<String> = header.iter().filter(|line| !line.is_empty()).cloned().collect();
+            let mut message:Vec<String> = message.into_iter().filter(|line| !line.is_empty()).collect();
+            let room = raws-items.len();
+            let fits_hint = header.len()+message.len() < room;
+            message.truncate(room);
+            header.truncate(room-message.len());
+            lines = header;
+            lines.extend(message);
+            lines.extend(items);
+            if fits_hint{
+                lines.push(hint.to_string());
+            }
+        }
+        lines.iter().map(|line| line.chars().take(columns).collect::<String>()).collect::<Vec<String>>().join("\n")
+    }
+    fn board(&self,config:&Config)->Board{
+        let dimensions = get_terminal_dimensions().unwrap_or((0,0));
+        let mut board = Board::from_config(config, dimensions, BlockType::Otype, Vec::new());
+        board.show_blocks = false;
+        board
+    }
+    /// change the value of the item,true when the screen has to be laid out again
+    fn change(&mut self,item:Item,config:&mut Config,step:isize)->bool{
+        match item {
+            Item::Mode => config.rules.mode = cycle(&Mode::ALL, &config.rules.mode, step),
+            Item::Speed => config.rules.start_speed = (config.rules.start_speed as isize+step).clamp(1, 30) as u32,
+            Item::Randomizer => config.rules.randomizer = cycle(&Randomizer::ALL, &config.rules.randomizer, step),
+            Item::Profile => {
+                let mut names = Profile::available();
+                if !names.contains(&config.profile){
+                    names.push(config.profile.clone());
+                    names.sort();
+                }
+                config.profile = cycle(&names, &config.profile, step);
+            },
+            Item::Theme => {
+                let themes = Theme::available();
+                match Theme::load(&cycle(&themes, &config.theme.name, step)) {
+                    Ok(theme) => config.theme = theme,
+                    Err(e) => self.message = Some(e)
+                }
+                return true;
+            },
+            Item::ReplayFile => self.replay = (self.replay as isize+step).rem_euclid(self.replays.len().max(1) as isize) as usize,
+            Item::Ghost => config.ghost = !config.ghost,
+            Item::HalfBlocks => {
+                config.half_blocks = !config.half_blocks;
+                return true;
+            },
+            _ => {}
+        }
+        false
+    }
+    /// show the header and the items on the board until an item that ends the menu is picked,
+    /// the settings are changed on the way
+    fn pick(&mut self,board:&mut Board,header:&dyn Fn(&Menu,&Config)->Vec<String>,config:&mut Config)->Item{
+        let mut changed = true;
+        loop {
+            if terminal::signalled().is_some(){
+                return Item::Quit;
+            }
+            if terminal::take_resized(){
+                board.layout(get_terminal_dimensions().unwrap_or((0,0)));
+                changed = true;
+            }
+            if changed{
+                board.banner = Some(self.banner(&header(self,config), config, board.well_size()));
+                board.draw();
+            }
+            std::thread::sleep(std::time::Duration::from_millis(TICK as u64));
+            let key = read_key(self.keys);
+            let item = self.items[self.selected];
+            changed = true;
+            match (key,item) {
+                (Key::Closed,_) => return Item::Quit,
+                (Key::Nothing,_) => changed = false,
+                (Key::Up,_) => self.selected = (self.selected+self.items.len()-1)%self.items.len(),
+                (Key::Down,_) => self.selected = (self.selected+1)%self.items.len(),
+                (Key::Select,Item::Settings) => {
+                    self.items = &SETTINGS;
+                    self.selected = 0;
+                },
+                (Key::Quit,_) | (Key::Select,Item::Back) if self.items == SETTINGS => {
+                    self.items = &MAIN;
+                    self.selected = MAIN.iter().position(|item| *item == Item::Settings).unwrap();
+                },
+                (Key::Select,Item::Play | Item::Quit | Item::Retry | Item::NewSeed | Item::Menu | Item::HighScores | Item::Back | Item::Profile | Item::NewProfile | Item::SaveReplay | Item::Replays | Item::Watch) => return item,
+                (Key::Quit,_) => return self.items[self.items.len()-1],
+                (Key::Left,_) => if self.change(item, config, -1){
+                    *board = self.board(config);
+                },
+                (Key::Right | Key::Select,_) => if self.change(item, config, 1){
+                    *board = self.board(config);
+                },
+                _ => changed = false
+            }
+            if item == Item::Speed{
+                board.draw_speed(config.rules.start_speed);
+            }
+        }
+    }
+    /// show the title screen until the player starts a game or quits
+    pub fn run(&mut self,config:&mut Config,scores:&Scores)->Choice{
+        if self.items != MAIN{
+            self.items = &MAIN;
+            self.selected = 0;
+        }
+        let mut board = self.board(config);
+        loop {
+            match self.pick(&mut board, &|_,_| vec!["block rain".to_string()], config) {
+                Item::Play => return Choice::Play,
+                Item::HighScores => if !self.high_scores(&mut board, config, scores, &HIGH_SCORES){
+                    return Choice::Quit;
+                },
+                Item::Profile => if !self.profile(&mut board, config){
+                    return Choice::Quit;
+                },
+                Item::Replays => {
+                    if !self.replays(&mut board, config){
+                        return Choice::Quit;
+                    }
+                    board = self.board(config);
+                },
+                _ => return Choice::Quit
+            }
+        }
+    }
+    /// show how the game on the board went until the player picks what comes next
+    pub fn results(&mut self,board:&mut Board,results:&Results,config:&mut Config,scores:&Scores,replay:&Replay)->AfterGame{
+        self.items = &RESULTS;
+        self.selected = 0;
+        board.show_blocks = false;
+        loop {
+            match self.pick(board, &|_,_| results.lines(), config) {
+                Item::Retry => return AfterGame::Retry,
+                Item::NewSeed => return AfterGame::NewSeed,
+                Item::Menu => return AfterGame::Menu,
+                Item::HighScores => if !self.high_scores(board, config, scores, &GAME_SCORES){
+                    return AfterGame::Quit;
+                },
+                Item::SaveReplay => self.message = Some(replay.save(&replay.name()).map_or_else(|e| e, |_| "replay saved".to_string())),
+                _ => return AfterGame::Quit
+            }
+        }
+    }
+    /// show the top scores of the mode until back is picked,
+    /// then go back to the items before,false when the game has to quit
+    fn high_scores(&mut self,board:&mut Board,config:&mut Config,scores:&Scores,items:&'static [Item])->bool{
+        let (before,selected) = (self.items,self.selected);
+        self.items = items;
+        self.selected = items.len()-1;
+        let columns = board.well_size().1;
+        let table = |_:&Menu,config:&Config| {
+            let mode = config.rules.mode;
+            // the short titles for narrow wells
+            let title = format!("{} top {}",mode.name(),crate::scores::TOP);
+            let mut lines = vec![if title.len() > columns {mode.name().to_string()} else {title},String::new()];
+            let top = scores.top(mode);
+            if top.is_empty(){
+                lines.push(if columns < 13 {"no scores"} else {"no scores yet"}.to_string());
+            }
+            lines.extend(top.iter().enumerate().map(|(index,score)| score.row(index+1, columns)));
+            lines
+        };
+        let back = self.pick(board, &table, config) == Item::Back;
+        (self.items,self.selected) = (before,selected);
+        back
+    }
+    /// show the statistics of the profile until back is picked,a new profile can be started on the way,
+    /// false when the game has to quit
+    fn profile(&mut self,board:&mut Board,config:&mut Config)->bool{
+        let (before,selected) = (self.items,self.selected);
+        self.items = &PROFILE;
+        self.selected = PROFILE.len()-1;
+        let stats = |_:&Menu,config:&Config| Profile::load(&config.profile).map_or_else(|e| vec![e], |profile| profile.lines());
+        let back = loop {
+            match self.pick(board, &stats, config) {
+                Item::NewProfile => match self.prompt(board, "new profile\n\nname", "") {
+                    Some(name) => if let Some(name) = profiles::clean_name(&name){
+                        config.profile = name;
+                        self.selected = PROFILE.len()-1;
+                    },
+                    None => break false
+                },
+                item => break item == Item::Back
+            }
+        };
+        (self.items,self.selected) = (before,selected);
+        back
+    }
+    /// list the saved replays until back is picked,watch plays the one shown,
+    /// false when the game has to quit
+    fn replays(&mut self,board:&mut Board,config:&mut Config)->bool{
+        let (before,selected) = (self.items,self.selected);
+        self.items = &REPLAYS;
+        self.selected = 0;
+        self.replays = Replay::available();
+        self.replay = 0;
+        // the name cut in pieces that fit in the well
+        let header = |menu:&Menu,_:&Config| {
+            let mut lines = vec!["replays".to_string(),String::new()];
+            match menu.replays.get(menu.replay) {
+                Some(name) => lines.extend(name.chars().collect::<Vec<char>>().chunks(18).map(|chunk| chunk.iter().collect())),
+                None => lines.push("no replays yet".to_string())
+            }
+            lines
+        };
+        let back = loop {
+            match self.pick(board, &header, config) {
+                Item::Watch => {
+                    let Some(name) = self.replays.get(self.replay) else {
+                        continue;
+                    };
+                    self.message = None;
+                    match Replay::saved(name) {
+                        Ok(replay) => {
+                            // the well and the rules of the replay,only while it plays
+                            let mut watched = config.clone();
+                            replay.apply(&mut watched);
+                            if let Err(e) = Player::new(&replay, &watched, self.keys).map(|mut player| player.run()){
+                                self.message = Some(e.to_string());
+                            }
+                            if terminal::signalled().is_some(){
+                                break false;
+                            }
+                        },
+                        Err(e) => self.message = Some(e)
+                    }
+                    *board = self.board(config);
+                },
+                item => break item == Item::Back
+            }
+        };
+        (self.items,self.selected) = (before,selected);
+        back
+    }
+    /// ask for a line of text under the title,starting from text,
+    /// None when the game has to quit
+    pub fn prompt(&mut self,board:&mut Board,title:&str,text:&str)->Option<String>{
+        let mut name = text.chars().filter(|c| c.is_ascii_graphic() || *c == ' ').take(NAME_LENGTH).collect::<String>();
+        let mut changed = true;
+        loop {
+            if terminal::signalled().is_some(){
+                return None;
+            }
+            if terminal::take_resized(){
+                board.layout(get_terminal_dimensions().unwrap_or((0,0)));
+                changed = true;
+            }
+            if changed{
+                board.banner = Some(format!("{}\n{:<width$}\n\nenter done",title,name.clone()+"_",width = NAME_LENGTH+1));
+                board.draw();
+                changed = false;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(TICK as u64));
+            // a whole name can be pasted between two ticks
+            loop {
+                match self.keys.try_recv() {
+                    Ok(b'\r' | b'\n') => return Some(name.trim().to_string()),
+                    Ok(0x7f | 0x08) => changed |= name.pop().is_some(),
+                    Ok(byte @ 0x20..=0x7e) if name.len() < NAME_LENGTH => {
+                        name.push(byte as char);
+                        changed = true;
+                    },
+                    Ok(_) => {},
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return None
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn banner_marks_the_selection_and_fits(){
+        let (_,keys) = std::sync::mpsc::channel();
+        let mut menu = Menu::new(&keys);
+        let config = Config::default();
+        menu.selected = 2;
+        let title = ["block rain".to_string()];
+        let text = menu.banner(&title, &config, (20,20));
+        assert!(text.contains("> speed: 5"));
+        assert_eq!(text.lines().count(),13);
+        menu.message = Some("You lose\nscore 10".to_string());
+        // blank lines go first,then the hint,then the title
+        assert_eq!(menu.banner(&title, &config, (13,20)).lines().count(),13);
+        assert!(menu.banner(&title, &config, (13,20)).contains("enter"));
+        assert!(!menu.banner(&title, &config, (12,20)).contains("enter"));
+        assert!(!menu.banner(&title, &config, (11,20)).contains("block rain"));
+        // a half blocks well of 8 raws by 10 columns keeps the items in view with short labels
+        menu.selected = 8;
+        let text = menu.banner(&title, &config, (8,10));
+        assert_eq!(text.lines().count(),8);
+        assert!(text.lines().all(|line| line.chars().count() <= 10));
+        assert!(text.contains(">quit") && text.contains(" random") && !text.lines().any(|line| line.trim() == "play"));
+        assert_eq!(cycle(&Mode::ALL, &Mode::Marathon, -1),Mode::Endless);
+        // the replays go round both ways
+        menu.replays = vec!["last".to_string(),"marathon".to_string()];
+        menu.change(Item::ReplayFile, &mut Config::default(), -1);
+        assert_eq!(menu.label(Item::ReplayFile, &config),"replay 2/2");
+    }
+    #[test]
+    fn results_keep_every_item_in_a_short_well(){
+        let (_,keys) = std::sync::mpsc::channel();
+        let mut menu = Menu::new(&keys);
+        let config = Config::default();
+        menu.items = &RESULTS;
+        menu.message = Some("replay saved".to_string());
+        let results = Results { title: "You lose", reason: "block out,the blocks could not enter", score: 120, lines: 12, speed: 6,
+            time: 95000, pieces: 40, tetrises: 2, t_spins: 1, record: Some(3) };
+        assert!(results.lines().contains(&"lines 12 speed 6".to_string()));
+        let text = menu.banner(&results.lines(), &config, (10,20));
+        assert_eq!(text.lines().count(),10);
+        assert!(text.lines().all(|line| line.chars().count() <= 20));
+        assert!(RESULTS.iter().all(|item| text.contains(&menu.label(*item, &config))));
+        assert!(text.starts_with("You lose\nblock out\nrecord #3\nscore 120\nreplay saved\n"));
+    }
+}
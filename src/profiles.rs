@@ -0,0 +1,136 @@
+use std::path::PathBuf;
+use crate::rules::Mode;
+
+/// A player and what they did in all their games,kept across sessions
+#[derive(Clone,PartialEq,Debug)]
+pub struct Profile {
+    pub name:String,
+    pub games:u32,
+    pub lines:u32,
+    pub tetrises:u32,
+    pub t_spins:u32,
+    /// milliseconds played in all games
+    pub time:u64,
+    /// for each mode in Mode::ALL,the fastest win,or the longest game in endless
+    best:[Option<u32>;3],
+}
+impl Profile {
+    fn new(name:&str)->Profile{
+        Profile { name: name.to_string(), games: 0, lines: 0, tetrises: 0, t_spins: 0, time: 0, best: [None;3] }
+    }
+    /// the saved profile,a new one when it was never saved
+    pub fn load(name:&str)->Result<Profile,String>{
+        match profiles_dir().map(|dir| dir.join(format!("{}.profile",name))) {
+            Some(path) if path.is_file() => {
+                let text = std::fs::read_to_string(&path).map_err(|e| format!("can not read profile {}: {}",name,e))?;
+                Profile::parse(name, &text)
+            },
+            _ => Ok(Profile::new(name))
+        }
+    }
+    /// names of the saved profiles
+    pub fn available()->Vec<String>{
+        crate::config::files_with_extension(profiles_dir(), "profile")
+    }
+    fn parse(name:&str,text:&str)->Result<Profile,String>{
+        let mut profile = Profile::new(name);
+        crate::config::read_settings(text, |key,value| profile.set(key, value)).map_err(|e| format!("profile {}: {}",name,e))?;
+        Ok(profile)
+    }
+    fn set(&mut self,key:&str,value:&str)->Result<(),String>{
+        let number = |value:&str| value.parse::<u64>().map_err(|_| format!("{} should be a number",key));
+        match key {
+            "games" => self.games = number(value)? as u32,
+            "lines" => self.lines = number(value)? as u32,
+            "tetrises" => self.tetrises = number(value)? as u32,
+            "t_spins" => self.t_spins = number(value)? as u32,
+            "time" => self.time = number(value)?,
+            _ => match key.strip_prefix("best_").and_then(Mode::parse) {
+                Some(mode) => self.best[mode_index(mode)] = Some(number(value)? as u32),
+                None => return Err(format!("unknown key: {}",key))
+            }
+        }
+        Ok(())
+    }
+    fn encode(&self)->String{
+        let mut text = format!("games = {}\nlines = {}\ntetrises = {}\nt_spins = {}\ntime = {}\n",
+            self.games,self.lines,self.tetrises,self.t_spins,self.time);
+        for mode in Mode::ALL{
+            if let Some(best) = self.best(mode){
+                text += &format!("best_{} = {}\n",mode.name(),best);
+            }
+        }
+        text
+    }
+    pub fn save(&self)->Result<(),String>{
+        let dir = profiles_dir().ok_or("no home directory for the profiles")?;
+        std::fs::create_dir_all(&dir).and_then(|_| std::fs::write(dir.join(format!("{}.profile",self.name)), self.encode()))
+            .map_err(|e| format!("can not save profile {}: {}",self.name,e))
+    }
+    /// milliseconds of the fastest win of the mode,or of the longest game in endless
+    pub fn best(&self,mode:Mode)->Option<u32>{
+        self.best[mode_index(mode)]
+    }
+    /// count a finished game of the mode
+    pub fn add(&mut self,mode:Mode,won:bool,lines:u32,tetrises:u32,t_spins:u32,time:u32){
+        self.games += 1;
+        self.lines += lines;
+        self.tetrises += tetrises;
+        self.t_spins += t_spins;
+        self.time += time as u64;
+        let best = &mut self.best[mode_index(mode)];
+        match mode {
+            Mode::Endless => *best = Some(best.map_or(time, |best| best.max(time))),
+            _ if won => *best = Some(best.map_or(time, |best| best.min(time))),
+            _ => {}
+        }
+    }
+    /// the lines of the profile screen
+    pub fn lines(&self)->Vec<String>{
+        let mut lines = vec![self.name.clone(),String::new(),format!("games {}",self.games),format!("lines {}",self.lines),
+            format!("tetrises {}",self.tetrises),format!("t-spins {}",self.t_spins),format!("played {}",clock(self.time))];
+        for mode in Mode::ALL{
+            let best = self.best(mode).map_or("-".to_string(), |best| clock(best as u64));
+            lines.push(format!("{} {}",mode.name(),best));
+        }
+        lines
+    }
+}
+
+/// a name that can be used as a file name,None when nothing is left of it
+pub fn clean_name(name:&str)->Option<String>{
+    let name:String = name.trim().chars().map(|c| if c.is_ascii_alphanumeric() || "-_ ".contains(c) {c} else {'_'}).collect();
+    (!name.is_empty()).then_some(name)
+}
+fn mode_index(mode:Mode)->usize{
+    Mode::ALL.iter().position(|other| *other == mode).unwrap()
+}
+/// h:mm:ss,or m:ss under an hour
+fn clock(ms:u64)->String{
+    let seconds = ms/1000;
+    if seconds >= 3600 {format!("{}:{:02}:{:02}",seconds/3600,seconds/60%60,seconds%60)} else {format!("{}:{:02}",seconds/60,seconds%60)}
+}
+/// $XDG_DATA_HOME/block_rain/profiles,or ~/.local/share/block_rain/profiles
+fn profiles_dir()->Option<PathBuf>{
+    Some(crate::config::data_dir()?.join("profiles"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adds_games_and_keeps_the_best(){
+        let mut profile = Profile::new("ann");
+        profile.add(Mode::Sprint, true, 40, 2, 1, 90000);
+        profile.add(Mode::Sprint, true, 40, 0, 0, 80000);
+        profile.add(Mode::Sprint, false, 12, 0, 0, 10000);
+        profile.add(Mode::Endless, false, 3, 0, 0, 5000);
+        assert_eq!((profile.games,profile.lines,profile.tetrises,profile.time),(4,95,2,185000));
+        assert_eq!((profile.best(Mode::Sprint),profile.best(Mode::Endless),profile.best(Mode::Marathon)),(Some(80000),Some(5000),None));
+        assert_eq!(Profile::parse("ann", &profile.encode()),Ok(profile.clone()));
+        assert!(profile.lines().contains(&"sprint 1:20".to_string()));
+        assert_eq!(clean_name(" a/b "),Some("a_b".to_string()));
+        assert_eq!(clean_name("  "),None);
+    }
+}
@@ -0,0 +1,389 @@
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver,TryRecvError};
+use crate::config::Config;
+use crate::menu::Menu;
+use crate::rules::Rules;
+use crate::terminal::{self,StartError};
+use crate::{Game,Key,TICK};
+use raw_terminal::get_terminal_dimensions;
+
+/// speeds a replay plays at,x1 is the speed it was played at
+const SPEEDS:[(&str,f32);6] = [("x.25",0.25),("x.5",0.5),("x1",1.0),("x2",2.0),("x4",4.0),("x8",8.0)];
+/// ticks a key stays lit in the overlay
+const SHOWN:u32 = 4;
+
+/// Everything needed to play a game again,the dealer and the well start from the same seed and settings
+/// and the same keys come at the same ticks
+pub struct Replay {
+    /// of the game that recorded it
+    pub version:String,
+    pub seed:u64,
+    pub width:usize,
+    pub height:usize,
+    pub hidden:usize,
+    pub rules:Rules,
+    /// the keys with the tick they were read at,in order
+    pub inputs:Vec<(u32,Key)>,
+    /// ticks the game played,the tick it was won or lost in counts,the tick it was quit in does not
+    pub end:u32,
+}
+impl Replay {
+    pub fn new(config:&Config,seed:u64)->Replay{
+        Replay { version: env!("CARGO_PKG_VERSION").to_string(), seed, width: config.width, height: config.height, hidden: config.hidden,
+            rules: config.rules.clone(), inputs: Vec::new(), end: 0 }
+    }
+    /// keep the key if it moves the falling blocks
+    pub fn record(&mut self,tick:u32,key:Key){
+        if letter(key).is_some(){
+            self.inputs.push((tick,key));
+        }
+    }
+    /// the game stopped at tick,played through when it ended there,before it when it was quit
+    pub fn finish(&mut self,tick:u32,ended:bool){
+        self.end = if ended {tick+1} else {tick};
+    }
+    /// read a replay file
+    pub fn load(path:&str)->Result<Replay,String>{
+        let text = std::fs::read_to_string(path).map_err(|e| format!("can not read replay {}: {}",path,e))?;
+        Replay::parse(&text).map_err(|e| format!("replay {}: {}",path,e))
+    }
+    fn parse(text:&str)->Result<Replay,String>{
+        // the settings are checked as the config file checks them
+        let mut config = Config::default();
+        let mut replay = Replay::new(&config, 0);
+        // without it nothing would be played
+        let mut end = None;
+        crate::config::read_settings(text, |key,value| {
+            match key {
+                "version" => replay.version = value.to_string(),
+                "seed" => replay.seed = value.parse().map_err(|_| "seed should be a number")?,
+                "end" => end = Some(value.parse().map_err(|_| "end should be a number")?),
+                "inputs" => {
+                    let mut tick:u32 = 0;
+                    for input in value.split_whitespace(){
+                        let (step,c) = input.split_at(input.len()-input.chars().last().map_or(0, char::len_utf8));
+                        let key = c.chars().next().and_then(from_letter).ok_or(format!("bad input: {}",input))?;
+                        tick = step.parse::<u32>().ok().and_then(|step| tick.checked_add(step)).ok_or(format!("bad input: {}",input))?;
+                        replay.inputs.push((tick,key));
+                    }
+                },
+                key => config.set(key, value)?
+            }
+            Ok(())
+        })?;
+        replay.end = end.ok_or("missing end")?;
+        (replay.width,replay.height,replay.hidden,replay.rules) = (config.width,config.height,config.hidden,config.rules);
+        Ok(replay)
+    }
+    /// read the replay saved as name
+    pub fn saved(name:&str)->Result<Replay,String>{
+        let dir = replays_dir().ok_or("no home directory for the replays")?;
+        Replay::load(&dir.join(format!("{}.replay",name)).to_string_lossy())
+    }
+    /// names of the saved replays,sorted
+    pub fn available()->Vec<String>{
+        crate::config::files_with_extension(replays_dir(), "replay")
+    }
+    /// play it with the well and the rules it was recorded with
+    pub fn apply(&self,config:&mut Config){
+        (config.width,config.height,config.hidden,config.rules) = (self.width,self.height,self.hidden,self.rules.clone());
+    }
+    /// `key = value` lines,the settings use the names of the config file,
+    /// end is the ticks played,the inputs are ticks since the last input followed by the key
+    fn encode(&self)->String{
+        let rules = &self.rules;
+        let mut text = format!("# block_rain replay\nversion = {}\nseed = {}\nwidth = {}\nheight = {}\nhidden = {}\n",
+            self.version,self.seed,self.width,self.height,self.hidden);
+        text += &format!("partial_lock_out = {}\nline_clear_delay = {}\nentry_delay = {}\nmode = {}\nspeed = {}\nrandomizer = {}\n",
+            rules.partial_lock_out,rules.line_clear_delay,rules.entry_delay,rules.mode.name(),rules.start_speed,rules.randomizer.name());
+        text += &format!("end = {}\ninputs =",self.end);
+        let mut last = 0;
+        for (tick,key) in &self.inputs{
+            text += &format!(" {}{}",tick-last,letter(*key).unwrap());
+            last = *tick;
+        }
+        text.push('\n');
+        text
+    }
+    /// write it to the replays directory as name.replay
+    pub fn save(&self,name:&str)->Result<(),String>{
+        let dir = replays_dir().ok_or("no home directory for the replays")?;
+        std::fs::create_dir_all(&dir).and_then(|_| std::fs::write(dir.join(format!("{}.replay",name)), self.encode()))
+            .map_err(|e| format!("can not save replay: {}",e))
+    }
+    /// a name that differs from game to game,mode,date and seed
+    pub fn name(&self)->String{
+        format!("{}-{}-{}",self.rules.mode.name(),crate::scores::today(),self.seed)
+    }
+}
+
+/// the key on the keyboard that gives the input
+fn letter(key:Key)->Option<char>{
+    match key {
+        Key::Left => Some('a'),
+        Key::Right => Some('d'),
+        Key::Up => Some('w'),
+        Key::Down => Some('s'),
+        _ => None
+    }
+}
+/// the input the key on the keyboard gives
+fn from_letter(c:char)->Option<Key>{
+    [Key::Left,Key::Right,Key::Up,Key::Down].into_iter().find(|key| letter(*key) == Some(c))
+}
+
+/// Plays a replay through the game on the terminal.
+/// space pauses,. plays one tick,+ and - change the speed,g goes to a piece and q quits
+pub struct Player<'a>{
+    replay:&'a Replay,
+    config:&'a Config,
+    keys:&'a Receiver<u8>,
+    game:Game<'a>,
+    /// the input to play next
+    next:usize,
+    /// index in SPEEDS
+    speed:usize,
+    paused:bool,
+    /// play one tick while paused
+    step:bool,
+    /// the game ended or all its ticks were played
+    over:bool,
+}
+impl<'a> Player<'a> {
+    pub fn new(replay:&'a Replay,config:&'a Config,keys:&'a Receiver<u8>)->Result<Player<'a>,StartError>{
+        let game = Game::new(keys, config, replay.seed)?;
+        Ok(Player { replay, config, keys, game, next: 0, speed: 2, paused: true, step: false, over: false })
+    }
+    /// blocks dealt so far,the falling one is the last of them
+    fn piece(&self)->u32{
+        self.game.stats.iter().sum()
+    }
+    /// play one tick with the keys recorded up to it,false when there is none left
+    fn tick(&mut self)->bool{
+        let tick = self.game.time/TICK;
+        if self.over || tick >= self.replay.end{
+            self.over = true;
+            return false;
+        }
+        // a file written by hand can have several keys on a tick,they wait in the game as pressed keys do
+        while let Some(&(_,key)) = self.replay.inputs.get(self.next).filter(|(at,_)| *at <= tick){
+            self.game.press(key);
+            self.next += 1;
+        }
+        self.over = self.game.step(Key::Nothing).is_some();
+        !self.over
+    }
+    /// play from the start or on,without drawing,until the piece falls
+    fn seek(&mut self,piece:u32){
+        if piece <= self.piece(){
+            let board = &self.game.game_board;
+            self.game = Game::with_dimensions(self.keys, self.config, self.replay.seed, (board.raws as u16,board.columns as u16));
+            self.next = 0;
+            self.over = false;
+        }
+        while self.piece() < piece && self.tick(){}
+    }
+    /// play a tick unless paused,the terminal too small counts as paused
+    fn play(&mut self){
+        if (!self.paused || self.step) && !self.game.game_board.too_small{
+            self.tick();
+            self.step = false;
+        }
+    }
+    /// what the player is doing,the keys of the last ticks,the speed and the piece,
+    /// on two lines when one does not fit in the well
+    fn overlay(&self)->String{
+        let tick = self.game.time/TICK;
+        let lit:Vec<Key> = self.replay.inputs[..self.next].iter().rev().take_while(|(at,_)| at+SHOWN > tick).map(|(_,key)| *key).collect();
+        let keys:String = [(Key::Left,'<'),(Key::Right,'>'),(Key::Up,'^'),(Key::Down,'v')].iter()
+            .map(|(key,c)| if lit.contains(key) {*c} else {' '}).collect();
+        let state = if self.over {"end"} else if self.paused {"||"} else {">>"};
+        let line = format!("{:<3}{} {} #{}",state,keys,SPEEDS[self.speed].0,self.piece());
+        if line.chars().count() <= self.game.game_board.well_size().1{
+            return line;
+        }
+        let state = if self.over {'.'} else if self.paused {'|'} else {'>'};
+        format!("{}{} #{}\n{}",state,SPEEDS[self.speed].0,self.piece(),keys)
+    }
+    fn help(&self)->String{
+        let version = if self.replay.version == env!("CARGO_PKG_VERSION") {String::new()} else {format!("\nrecorded by {}",self.replay.version)};
+        format!("replay{}\n\nspace play\n. step\n+ - speed\ng go to piece\nq quit",version)
+    }
+    /// act on a key of the player,false when the replay has to stop
+    fn press(&mut self,byte:u8)->bool{
+        match byte {
+            b' ' | b'p' => {
+                self.paused = !self.paused;
+                self.game.game_board.banner = None;
+            },
+            b'.' => {
+                self.paused = true;
+                self.step = true;
+                self.game.game_board.banner = None;
+            },
+            b'+' | b'=' => self.speed = (self.speed+1).min(SPEEDS.len()-1),
+            b'-' => self.speed = self.speed.saturating_sub(1),
+            b'g' if !self.game.game_board.too_small => {
+                let piece = Menu::new(self.keys).prompt(&mut self.game.game_board, "go to piece", "");
+                let Some(piece) = piece else {
+                    return false;
+                };
+                self.game.game_board.banner = None;
+                if let Ok(piece) = piece.parse(){
+                    self.seek(piece);
+                }
+            },
+            b'q' => return false,
+            _ => {}
+        }
+        true
+    }
+    /// play until q is pressed or the terminal goes away
+    pub fn run(&mut self){
+        self.game.game_board.banner = Some(self.help());
+        loop {
+            if terminal::signalled().is_some(){
+                return;
+            }
+            if terminal::take_resized(){
+                self.game.game_board.layout(get_terminal_dimensions().unwrap_or((0,0)));
+            }
+            // every key since the last tick,a fast replay has short ticks
+            loop {
+                match self.keys.try_recv() {
+                    Ok(byte) => if !self.press(byte){
+                        return;
+                    },
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return
+                }
+            }
+            self.play();
+            self.game.game_board.overlay = Some(self.overlay());
+            self.game.game_board.draw();
+            std::thread::sleep(std::time::Duration::from_secs_f32(TICK as f32/1000.0/SPEEDS[self.speed].1));
+        }
+    }
+}
+
+/// $XDG_DATA_HOME/block_rain/replays,or ~/.local/share/block_rain/replays
+fn replays_dir()->Option<PathBuf>{
+    Some(crate::config::data_dir()?.join("replays"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_inputs_as_tick_steps(){
+        let mut replay = Replay::new(&Config::default(), 7);
+        replay.record(3, Key::Left);
+        replay.record(3, Key::Up);
+        replay.record(4, Key::Pause);
+        replay.record(10, Key::Down);
+        replay.end = 12;
+        let text = replay.encode();
+        assert!(text.contains("seed = 7\n"));
+        assert!(text.contains("mode = marathon\n"));
+        assert!(text.ends_with("end = 12\ninputs = 3a 0w 7s\n"));
+        let parsed = Replay::parse(&text).unwrap();
+        assert_eq!(parsed.encode(),text);
+        assert!(Replay::parse("end = 5\ninputs = 3x").is_err());
+        assert_eq!(Replay::parse("end = 5\ninputs = 4294967295a 2a").err(),Some("bad input: 2a".to_string()));
+        assert_eq!(Replay::parse("inputs = 3a").err(),Some("missing end".to_string()));
+        assert!(Replay::parse("width = 2").is_err());
+    }
+    #[test]
+    fn replay_plays_the_same_game(){
+        let (_,keys) = std::sync::mpsc::channel();
+        let config = Config::default();
+        let mut game = Game::with_dimensions(&keys, &config, 42, (40,80));
+        let input = |tick:u32| match tick%13 {
+            0 => Key::Left,
+            4 => Key::Up,
+            7 => Key::Right,
+            _ if tick.is_multiple_of(3) => Key::Down,
+            _ => Key::Nothing
+        };
+        let mut ended = false;
+        while !ended && game.time/TICK < 20000{
+            let tick = game.time/TICK;
+            game.replay.record(tick, input(tick));
+            ended = game.step(input(tick)).is_some();
+        }
+        assert!(ended);
+        game.replay.finish(game.time/TICK, ended);
+        let replay = Replay::parse(&game.replay.encode()).unwrap();
+        let mut again = Game::with_dimensions(&keys, &config, replay.seed, (40,80));
+        let mut inputs = replay.inputs.iter().peekable();
+        let mut outcome = None;
+        for tick in 0..replay.end{
+            assert!(outcome.is_none());
+            let key = inputs.next_if(|(at,_)| *at == tick).map_or(Key::Nothing, |(_,key)| *key);
+            outcome = again.step(key);
+        }
+        assert!(outcome.is_some());
+        let cells = |game:&Game| game.game_board.cells.iter().map(|cell| cell.map(|t| t as u8)).collect::<Vec<_>>();
+        assert_eq!(cells(&again),cells(&game));
+        assert_eq!((again.score,again.lines,again.pieces,again.stats),(game.score,game.lines,game.pieces,game.stats));
+    }
+    fn paused_player<'a>(replay:&'a Replay,config:&'a Config,keys:&'a Receiver<u8>)->Player<'a>{
+        let game = Game::with_dimensions(keys, config, replay.seed, (40,80));
+        Player { replay, config, keys, game, next: 0, speed: 2, paused: true, step: false, over: false }
+    }
+    #[test]
+    fn steps_and_seeks_through_the_inputs(){
+        let (_,keys) = std::sync::mpsc::channel();
+        let config = Config::default();
+        let mut replay = Replay::new(&config, 9);
+        // two keys on one tick both go in,one each tick
+        replay.inputs = vec![(0,Key::Right),(0,Key::Right),(5,Key::Up)];
+        replay.end = 100000;
+        let mut player = paused_player(&replay, &config, &keys);
+        let x = player.game.game_board.blocks_position.0;
+        player.play();
+        assert_eq!(player.game.time,0);
+        assert!(player.press(b'.'));
+        player.play();
+        player.play();
+        assert_eq!((player.game.time,player.next,player.game.game_board.blocks_position.0),(TICK,2,x+1));
+        player.press(b'.');
+        player.play();
+        assert_eq!(player.game.game_board.blocks_position.0,x+2);
+        player.seek(6);
+        let (time,cells) = (player.game.time,player.game.game_board.cells.iter().map(|cell| cell.map(|t| t as u8)).collect::<Vec<_>>());
+        assert_eq!(player.piece(),6);
+        // going back plays again from the start on a board as big as the one shown
+        player.seek(2);
+        assert_eq!(player.piece(),2);
+        player.seek(6);
+        assert_eq!(player.game.time,time);
+        assert_eq!(player.game.game_board.cells.iter().map(|cell| cell.map(|t| t as u8)).collect::<Vec<_>>(),cells);
+        assert!(!player.press(b'q'));
+    }
+    #[test]
+    fn speeds_go_up_from_x1_and_fit_the_well(){
+        let (_,keys) = std::sync::mpsc::channel();
+        let mut config = Config::default();
+        let replay = Replay::new(&config, 9);
+        assert_eq!(SPEEDS[2],("x1",1.0));
+        assert!(SPEEDS.windows(2).all(|pair| pair[0].1 < pair[1].1));
+        let mut player = paused_player(&replay, &config, &keys);
+        for _ in 0..10{
+            player.press(b'+');
+        }
+        assert_eq!(SPEEDS[player.speed].0,"x8");
+        for _ in 0..10{
+            player.press(b'-');
+        }
+        assert_eq!(SPEEDS[player.speed].0,"x.25");
+        assert_eq!(player.overlay(),"||      x.25 #1");
+        // a well of 10 columns takes the overlay on two lines
+        config.cell_size = (1,1);
+        let mut player = paused_player(&replay, &config, &keys);
+        player.speed = 0;
+        assert_eq!(player.overlay(),"|x.25 #1\n    ");
+        assert!(player.overlay().lines().all(|line| line.chars().count() <= 10));
+    }
+}
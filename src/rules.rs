@@ -0,0 +1,74 @@
+/// Rules that decide how a game plays out,apart from the size of the well
+#[derive(Clone)]
+pub struct Rules{
+    /// also top out when any pixel of the locked blocks is above the visible well,
+    /// lock out only ends the game when every pixel is
+    pub partial_lock_out:bool,
+    /// milliseconds the full raws are shown being wiped before they are removed
+    pub line_clear_delay:u32,
+    /// milliseconds between a blocks locking (or its raws being removed) and the next one spawning
+    pub entry_delay:u32,
+    pub mode:Mode,
+    pub randomizer:Randomizer,
+    /// speed at the start of a game,it goes up by one every 100 points
+    pub start_speed:u32,
+}
+impl Default for Rules {
+    fn default() -> Self {
+        Rules { partial_lock_out: false, line_clear_delay: 300, entry_delay: 100, mode: Mode::Marathon,
+            randomizer: Randomizer::Random, start_speed: 5 }
+    }
+}
+
+/// What a game is played for
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum Mode {
+    /// reach 500 points
+    Marathon,
+    /// clear 40 raws as fast as possible
+    Sprint,
+    /// play until topping out
+    Endless,
+}
+impl Mode {
+    pub const ALL:[Mode;3] = [Mode::Marathon,Mode::Sprint,Mode::Endless];
+    pub fn name(&self)->&'static str{
+        match self {
+            Mode::Marathon => "marathon",
+            Mode::Sprint => "sprint",
+            Mode::Endless => "endless",
+        }
+    }
+    pub fn parse(name:&str)->Option<Mode>{
+        Mode::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+    /// the game is won with this score and these cleared raws
+    pub fn won(&self,score:u32,lines:u32)->bool{
+        match self {
+            Mode::Marathon => score >= 500,
+            Mode::Sprint => lines >= 40,
+            Mode::Endless => false,
+        }
+    }
+}
+
+/// How the next blocks is picked
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum Randomizer {
+    /// any type,each time
+    Random,
+    /// every type once in a shuffled bag,then a new bag
+    Bag,
+}
+impl Randomizer {
+    pub const ALL:[Randomizer;2] = [Randomizer::Random,Randomizer::Bag];
+    pub fn name(&self)->&'static str{
+        match self {
+            Randomizer::Random => "random",
+            Randomizer::Bag => "bag",
+        }
+    }
+    pub fn parse(name:&str)->Option<Randomizer>{
+        Randomizer::ALL.into_iter().find(|randomizer| randomizer.name() == name)
+    }
+}
@@ -0,0 +1,171 @@
+use std::path::PathBuf;
+use crate::rules::Mode;
+
+/// best scores kept for each mode
+pub const TOP:usize = 10;
+/// longest name a player can enter
+pub const NAME_LENGTH:usize = 12;
+
+/// One finished game in the high score table
+#[derive(Clone,PartialEq,Debug)]
+pub struct Score {
+    pub mode:Mode,
+    pub score:u32,
+    pub lines:u32,
+    /// milliseconds played
+    pub time:u32,
+    /// the day it was played,YYYY-MM-DD
+    pub date:String,
+    pub seed:u64,
+    pub name:String,
+}
+impl Score {
+    /// true when self ranks above other,
+    /// sprint ranks by raws cleared and then by time,the other modes by score and then by time
+    fn beats(&self,other:&Score)->bool{
+        let key = |score:&Score| match score.mode {
+            Mode::Sprint => (score.lines.min(40),u32::MAX-score.time),
+            _ => (score.score,u32::MAX-score.time)
+        };
+        key(self) > key(other)
+    }
+    fn parse(line:&str)->Option<Score>{
+        let fields:Vec<&str> = line.split('\t').collect();
+        match fields[..] {
+            [mode,score,lines,time,date,seed,name] => Some(Score { mode: Mode::parse(mode)?, score: score.parse().ok()?, lines: lines.parse().ok()?,
+                time: time.parse().ok()?, date: date.to_string(), seed: seed.parse().ok()?, name: name.to_string() }),
+            _ => None
+        }
+    }
+    fn encode(&self)->String{
+        format!("{}\t{}\t{}\t{}\t{}\t{}\t{}",self.mode.name(),self.score,self.lines,self.time,self.date,self.seed,self.name)
+    }
+    /// a line of the table,rank,name and score or time in 18 columns,
+    /// the name is cut to fit in fewer and left out under 11
+    pub fn row(&self,rank:usize,columns:usize)->String{
+        let result = match self.mode {
+            Mode::Sprint if self.lines >= 40 => format!("{}:{:02}",self.time/60000,self.time/1000%60),
+            Mode::Sprint => format!("{}L",self.lines),
+            _ => self.score.to_string()
+        };
+        match columns.saturating_sub(10).min(8) {
+            0 => format!("{:>2} {:>6}",rank,result),
+            width => format!("{:>2} {:<width$} {:>6}",rank,self.name.chars().take(width).collect::<String>(),result,width = width)
+        }
+    }
+}
+
+/// The high scores of every mode,best first
+#[derive(Default)]
+pub struct Scores {
+    entries:Vec<Score>,
+}
+impl Scores {
+    /// the saved table,empty when there is none or it can not be read
+    pub fn load()->Scores{
+        let text = scores_path().and_then(|path| std::fs::read_to_string(path).ok()).unwrap_or_default();
+        Scores::parse(&text)
+    }
+    fn parse(text:&str)->Scores{
+        let mut scores = Scores::default();
+        for score in text.lines().filter(|line| !line.starts_with('#')).filter_map(Score::parse){
+            scores.insert(score);
+        }
+        scores
+    }
+    pub fn save(&self)->Result<(),String>{
+        let path = scores_path().ok_or("no home directory for the high scores")?;
+        if let Some(dir) = path.parent(){
+            std::fs::create_dir_all(dir).map_err(|e| format!("can not save high scores: {}",e))?;
+        }
+        let mut text = "# mode\tscore\tlines\ttime\tdate\tseed\tname\n".to_string();
+        for score in &self.entries{
+            text += &score.encode();
+            text.push('\n');
+        }
+        std::fs::write(&path, text).map_err(|e| format!("can not save high scores: {}",e))
+    }
+    /// the best scores of the mode,best first
+    pub fn top(&self,mode:Mode)->Vec<&Score>{
+        self.entries.iter().filter(|score| score.mode == mode).collect()
+    }
+    /// the rank from 1 the score would get,None when it is not among the best
+    pub fn rank(&self,score:&Score)->Option<usize>{
+        let rank = self.top(score.mode).iter().filter(|other| !score.beats(other)).count()+1;
+        (rank <= TOP).then_some(rank)
+    }
+    /// add the score if it is among the best of its mode
+    pub fn insert(&mut self,score:Score){
+        if self.rank(&score).is_none(){
+            return;
+        }
+        let index = self.entries.iter().position(|other| score.beats(other)).unwrap_or(self.entries.len());
+        let mode = score.mode;
+        self.entries.insert(index, score);
+        if let Some(last) = self.entries.iter().enumerate().filter(|(_,score)| score.mode == mode).nth(TOP).map(|(index,_)| index){
+            self.entries.remove(last);
+        }
+    }
+}
+
+/// $XDG_DATA_HOME/block_rain/scores,or ~/.local/share/block_rain/scores
+fn scores_path()->Option<PathBuf>{
+    Some(crate::config::data_dir()?.join("scores"))
+}
+
+/// today as YYYY-MM-DD in UTC
+pub fn today()->String{
+    let seconds = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    date(seconds/86400)
+}
+/// the civil date of a day counted from 1970-01-01
+fn date(days:u64)->String{
+    // from Howard Hinnant's days_from_civil inverse,in eras of 400 years
+    let days = days as i64+719468;
+    let era = days/146097;
+    let day_of_era = days-era*146097;
+    let year_of_era = (day_of_era-day_of_era/1460+day_of_era/36524-day_of_era/146096)/365;
+    let day_of_year = day_of_era-(365*year_of_era+year_of_era/4-year_of_era/100);
+    let month = (5*day_of_year+2)/153;
+    let day = day_of_year-(153*month+2)/5+1;
+    let month = if month < 10 {month+3} else {month-9};
+    let year = year_of_era+era*400+if month <= 2 {1} else {0};
+    format!("{:04}-{:02}-{:02}",year,month,day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(mode:Mode,score:u32,lines:u32,time:u32)->Score{
+        Score { mode, score, lines, time, date: "2026-01-01".to_string(), seed: 1, name: "a".to_string() }
+    }
+
+    #[test]
+    fn keeps_the_best_of_each_mode(){
+        let mut scores = Scores::default();
+        for points in 0..15{
+            scores.insert(score(Mode::Marathon, points*10, points, 1000));
+        }
+        scores.insert(score(Mode::Sprint, 0, 40, 90000));
+        scores.insert(score(Mode::Sprint, 0, 40, 60000));
+        scores.insert(score(Mode::Sprint, 0, 30, 10000));
+        let marathon = scores.top(Mode::Marathon);
+        assert_eq!(marathon.len(),TOP);
+        assert_eq!((marathon[0].score,marathon[9].score),(140,50));
+        assert_eq!(scores.rank(&score(Mode::Marathon, 40, 0, 0)),None);
+        assert_eq!(scores.rank(&score(Mode::Marathon, 100, 0, 0)),Some(5));
+        let sprint:Vec<u32> = scores.top(Mode::Sprint).iter().map(|score| score.time).collect();
+        assert_eq!(sprint,[60000,90000,10000]);
+        let text:String = scores.entries.iter().map(|score| score.encode()+"\n").collect();
+        assert_eq!(Scores::parse(&text).entries,scores.entries);
+        assert_eq!(scores.top(Mode::Sprint)[0].row(1,20)," 1 a          1:00");
+        assert_eq!(scores.top(Mode::Sprint)[0].row(1,10)," 1   1:00");
+    }
+    #[test]
+    fn dates_from_days(){
+        assert_eq!(date(0),"1970-01-01");
+        assert_eq!(date(20742),"2026-10-16");
+        assert_eq!(date(11016),"2000-02-29");
+    }
+}
@@ -0,0 +1,136 @@
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use raw_terminal::*;
+
+#[allow(non_camel_case_types)]
+type c_int = i32;
+type SigHandler = extern "C" fn(c_int);
+
+const SIGINT:c_int = 2;
+const SIGTERM:c_int = 15;
+const SIGWINCH:c_int = 28;
+
+#[link(name = "c")]
+extern "C"{
+    fn signal(signum:c_int,handler:SigHandler)->usize;
+}
+
+/// true while the terminal is in raw mode and must be restored
+static ACTIVE:AtomicBool = AtomicBool::new(false);
+/// number of the last termination signal received,0 if none
+static SIGNALLED:AtomicI32 = AtomicI32::new(0);
+/// the terminal changed its size since the last check
+static RESIZED:AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_terminate(signum:c_int){
+    // only touch an atomic here,the game loop notices it and shuts down normally
+    SIGNALLED.store(signum, Ordering::SeqCst);
+}
+extern "C" fn on_resize(_signum:c_int){
+    RESIZED.store(true, Ordering::SeqCst);
+}
+
+/// Why the game can not start on this terminal
+#[derive(Debug)]
+pub enum StartError {
+    /// stdin or stdout is not a terminal,e.g. piped or redirected
+    NoTty,
+    /// TERM is missing,there is no telling how to draw,a dumb terminal is drawn in mono
+    Unsupported,
+    /// the frame needs (raws,columns) but the terminal has less
+    TooSmall{needed:(usize,usize),got:(u16,u16)},
+}
+impl StartError {
+    /// exit status of the process,2 is taken by bad arguments
+    pub fn exit_code(&self)->i32{
+        match self {
+            StartError::NoTty => 3,
+            StartError::Unsupported => 4,
+            StartError::TooSmall{..} => 5,
+        }
+    }
+}
+impl std::fmt::Display for StartError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StartError::NoTty => write!(f,"block_rain needs a terminal,run it without redirecting stdin or stdout"),
+            StartError::Unsupported => write!(f,"TERM is not set,can not tell how to draw on this terminal"),
+            StartError::TooSmall{needed,got} => write!(f,"the terminal is {}x{} but the game needs {}x{},make it bigger or try --half-blocks true",
+                got.1,got.0,needed.1,needed.0),
+        }
+    }
+}
+
+/// make sure the game can be drawn here before touching the terminal
+pub fn check()->Result<(),StartError>{
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal(){
+        return Err(StartError::NoTty);
+    }
+    if std::env::var("TERM").unwrap_or_default().is_empty(){
+        return Err(StartError::Unsupported);
+    }
+    Ok(())
+}
+
+/// true to switch to the alternate screen,false to go back to the normal screen and its scrollback
+fn alternate_screen(enable:bool){
+    if enable{
+        print!("\x1b[?1049h\x1b[0m\x1b[2J\x1b[H");
+    }else {
+        print!("\x1b[0m\x1b[?1049l");
+    }
+}
+
+/// Puts the terminal into game mode on the alternate screen and puts it back when dropped.
+/// Also restores the terminal before a panic message is printed
+/// and turns Ctrl-C and SIGTERM into a request to quit,
+/// and SIGWINCH into a request to lay the screen out again.
+pub struct TerminalGuard;
+impl TerminalGuard {
+    pub fn new()->TerminalGuard{
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            restore();
+            previous(info);
+        }));
+        unsafe{
+            signal(SIGINT, on_terminate);
+            signal(SIGTERM, on_terminate);
+            signal(SIGWINCH, on_resize);
+        }
+        set_mode(false);
+        ACTIVE.store(true, Ordering::SeqCst);
+        // no full reset here,it would wipe the normal screen the player comes back to
+        alternate_screen(true);
+        hide_cursor(true);
+        TerminalGuard
+    }
+}
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+/// Undo everything the guard changed,safe to call more than once.
+pub fn restore(){
+    if ACTIVE.swap(false, Ordering::SeqCst){
+        alternate_screen(false);
+        hide_cursor(false);
+        set_mode(true);
+        let _ = std::io::stdout().flush();
+    }
+}
+
+/// The termination signal received so far,if any
+pub fn signalled()->Option<i32>{
+    match SIGNALLED.load(Ordering::SeqCst) {
+        0 => None,
+        signum => Some(signum)
+    }
+}
+
+/// true once after each change of the terminal size
+pub fn take_resized()->bool{
+    RESIZED.swap(false, Ordering::SeqCst)
+}
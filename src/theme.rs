@@ -0,0 +1,165 @@
+use std::path::PathBuf;
+use crate::BlockType;
+use crate::color::Color;
+
+/// How the well,the blocks and the sidebar look.
+/// Loaded from `key = value` files,see `Theme::set` for the keys.
+#[derive(Clone)]
+pub struct Theme{
+    pub name:String,
+    /// colors of the blocks in the order t,z,s,l,i,o
+    pub pieces:[Color;6],
+    /// the two characters a cell is drawn with
+    pub cell:[char;2],
+    /// the piece color goes to the background of the cell,else to its characters
+    pub fill_back:bool,
+    /// ┌ ┬ ┐ │ └ ┴ ┘ ─
+    pub border:[char;8],
+    pub border_color:Color,
+    /// front and back color of the next,score and speed labels
+    pub labels:[(Color,Color);3],
+    /// color of the numbers under the labels
+    pub value_color:Color,
+    /// color of the warning when the stack gets near the top
+    pub danger_color:Color,
+}
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            name: "classic".to_string(),
+            pieces: [BlockType::Ttype,BlockType::Ztype,BlockType::Stype,BlockType::Ltype,BlockType::Itype,BlockType::Otype].map(|t| t.get_color()),
+            cell: [' ',' '],
+            fill_back: true,
+            border: ['┌','┬','┐','│','└','┴','┘','─'],
+            border_color: Color::Default,
+            labels: [(Color::Default,Color::Red),(Color::Default,Color::Green),(Color::Default,Color::Blue)],
+            value_color: Color::Yellow,
+            danger_color: Color::Red,
+        }
+    }
+}
+
+/// + + + | + + + -
+pub const ASCII_BORDER:[char;8] = ['+','+','+','|','+','+','+','-'];
+
+/// themes that need no file
+const BUILTIN:[(&str,&str);4] = [
+    ("classic",""),
+    ("blocks","cell = ██\nfill = front\n"),
+    ("brackets","cell = []\nfill = front\nborder_color = bright_black\n"),
+    ("shade","cell = ▓▓\nfill = front\nborder = ╔╦╗║╚╩╝═\nlabels = black,white\n"),
+];
+
+impl Theme {
+    /// a builtin theme,a theme file in the themes directory or a path to a theme file
+    pub fn load(name:&str)->Result<Theme,String>{
+        if let Some((_,text)) = BUILTIN.iter().find(|(builtin,_)| *builtin == name){
+            return Theme::parse(name, text);
+        }
+        let path = match themes_dir().map(|dir| dir.join(format!("{}.theme",name))) {
+            Some(path) if path.is_file() => path,
+            _ => PathBuf::from(name)
+        };
+        let text = std::fs::read_to_string(&path).map_err(|e| format!("can not read theme {}: {}",name,e))?;
+        let stem = path.file_stem().map_or(name.to_string(), |stem| stem.to_string_lossy().into_owned());
+        Theme::parse(&stem, &text)
+    }
+    /// names of the builtin themes and of the files in the themes directory
+    pub fn available()->Vec<String>{
+        let mut names:Vec<String> = BUILTIN.iter().map(|(name,_)| name.to_string()).collect();
+        let files = crate::config::files_with_extension(themes_dir(), "theme");
+        names.extend(files.into_iter().filter(|name| !BUILTIN.iter().any(|(builtin,_)| builtin == name)));
+        names
+    }
+    /// the same theme with only ASCII characters,for terminals without Unicode
+    pub fn ascii(mut self)->Theme{
+        if !self.border.iter().all(char::is_ascii){
+            self.border = ASCII_BORDER;
+        }
+        if !self.cell.iter().all(char::is_ascii){
+            self.cell = ['[',']'];
+        }
+        self
+    }
+    fn parse(name:&str,text:&str)->Result<Theme,String>{
+        let mut theme = Theme { name: name.to_string(), ..Theme::default() };
+        crate::config::read_settings(text, |key,value| theme.set(key, value)).map_err(|e| format!("theme {}: {}",name,e))?;
+        Ok(theme)
+    }
+    fn set(&mut self,key:&str,value:&str)->Result<(),String>{
+        let color = |value:&str| Color::parse(value).ok_or(format!("{} is not a color",value));
+        let pair = |value:&str| match value.split_once(',') {
+            Some((front,back)) => Ok((color(front.trim())?,color(back.trim())?)),
+            None => Err(format!("{} should be two colors,front and back",key))
+        };
+        if let Some(index) = ["t","z","s","l","i","o"].iter().position(|piece| *piece == key){
+            self.pieces[index] = color(value)?;
+            return Ok(());
+        }
+        match key {
+            "cell" => {
+                let chars:Vec<char> = value.chars().collect();
+                self.cell = match chars[..] {
+                    [c] => [c,c],
+                    [left,right] => [left,right],
+                    _ => return Err("cell should be one or two characters".to_string())
+                };
+            },
+            "fill" => self.fill_back = match value {
+                "back" => true,
+                "front" => false,
+                _ => return Err("fill should be back or front".to_string())
+            },
+            "border" => {
+                let chars:Vec<char> = value.chars().collect();
+                self.border = chars.try_into().map_err(|_| "border should be 8 characters ┌┬┐│└┴┘─".to_string())?;
+            },
+            "border_color" => self.border_color = color(value)?,
+            "labels" => self.labels = [pair(value)?;3],
+            "next_label" => self.labels[0] = pair(value)?,
+            "score_label" => self.labels[1] = pair(value)?,
+            "speed_label" => self.labels[2] = pair(value)?,
+            "value_color" => self.value_color = color(value)?,
+            "danger_color" => self.danger_color = color(value)?,
+            _ => return Err(format!("unknown key {}",key))
+        }
+        Ok(())
+    }
+}
+
+/// $XDG_CONFIG_HOME/block_rain/themes,or ~/.config/block_rain/themes
+fn themes_dir()->Option<PathBuf>{
+    Some(crate::config::config_dir()?.join("themes"))
+}
+
+/// true when the locale of LC_ALL,LC_CTYPE or LANG uses UTF-8
+pub fn locale_is_utf8()->bool{
+    let locale = ["LC_ALL","LC_CTYPE","LANG"].iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    locale.contains("utf-8") || locale.contains("utf8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_theme_file(){
+        let theme = Theme::parse("mine", "# comment\ncell = []\nfill = front\ni = #00ffff\nborder = ++++++|-\nscore_label = white,bright_blue\ndanger_color = purple\n").unwrap();
+        assert_eq!(theme.cell,['[',']']);
+        assert!(!theme.fill_back);
+        assert_eq!(theme.pieces[BlockType::Itype as usize],Color::Rgb(0,255,255));
+        assert_eq!(theme.border[7],'-');
+        assert_eq!(theme.labels[1],(Color::White,Color::Bright(4)));
+        assert_eq!(theme.danger_color,Color::Purple);
+        assert!(Theme::parse("bad", "cell = abc").is_err());
+        assert!(Theme::parse("bad", "o = nope").is_err());
+        let ascii = Theme::load("shade").unwrap().ascii();
+        assert_eq!(ascii.border,ASCII_BORDER);
+        assert_eq!(ascii.cell,['[',']']);
+        assert_eq!(theme.ascii().border[7],'-');
+    }
+}